@@ -7,18 +7,21 @@ async fn main() -> anyhow::Result<()> {
     let client = FIOClient::new_from_env()?;
 
     let all_buildings = client.get_all_buildings().await?;
+    for rejected in &all_buildings.rejected {
+        eprintln!("Skipping building {:?}: {}", rejected.id, rejected.reason);
+    }
 
     let mut pre = quote! {
         let mut map = HashMap::new();
     };
 
-    let mut all_buildings: Vec<_> = all_buildings.into_iter().collect();
-    all_buildings.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut all_buildings: Vec<_> = all_buildings.entries.iter().collect();
+    all_buildings.sort_by(|a, b| a.0.cmp(b.0));
 
     for (_ticker, building) in all_buildings {
         let name = &building.name;
         let ticker = &building.ticker;
-        let expertise = if let Some(exp) = building.expertise {
+        let expertise = if let Some(exp) = &building.expertise {
             quote! { Some(#exp) }
         } else {
             quote! { None }
@@ -7,13 +7,16 @@ async fn main() -> anyhow::Result<()> {
     let client = FIOClient::new_from_env()?;
 
     let all_materials = client.get_all_materials().await?;
+    for rejected in &all_materials.rejected {
+        eprintln!("Skipping material {:?}: {}", rejected.id, rejected.reason);
+    }
 
     let mut pre = quote! {
         let mut map = HashMap::new();
     };
 
-    let mut all_materials: Vec<_> = all_materials.into_iter().collect();
-    all_materials.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut all_materials: Vec<_> = all_materials.entries.iter().collect();
+    all_materials.sort_by(|a, b| a.0.cmp(b.0));
 
     for (_ticker, mat) in all_materials.iter() {
         let material_id = &mat.material_id;
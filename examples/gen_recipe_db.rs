@@ -5,10 +5,14 @@ async fn main() -> anyhow::Result<()> {
     use quote::quote;
     let client = fiors::FIOClient::new_from_env()?;
 
-    let mut all_recipes: Vec<_> = client
+    let catalog = client
         .get_all_recipes()
         .await
         .context("Failed to get recipe list from API")?;
+    for rejected in &catalog.rejected {
+        eprintln!("Skipping recipe {:?}: {}", rejected.id, rejected.reason);
+    }
+    let mut all_recipes = catalog.entries.clone();
 
     all_recipes.sort_by(|a, b| a.standard_recipe_name.cmp(&b.standard_recipe_name));
 
@@ -49,7 +49,7 @@ async fn main() -> anyhow::Result<()> {
         .nth(1)
         .or(std::env::var("FIO_AUTH_TOKEN").ok())
         .unwrap();
-    let client = FIOClient::new_with_key(api_key);
+    let client = FIOClient::new_with_key(api_key)?;
 
     let username = client.is_auth().await?;
 
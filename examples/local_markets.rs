@@ -18,6 +18,7 @@ async fn main() -> anyhow::Result<()> {
     let client = FIOClient::new_from_env()?;
 
     let username = client.is_auth().await?;
+    let company_code = client.get_user_info(&username).await?.company_code;
 
     // let all_materials = client.get_all_materials().await?;
     // create a map from material name to category
@@ -60,7 +61,7 @@ async fn main() -> anyhow::Result<()> {
                 .map(|b| format!("{:.2}", 100.0 * price_per_unit / b))
                 .unwrap_or("N/A".into());
 
-            if ad.creator_company_code == "EM32" {
+            if ad.creator_company_code == company_code {
                 our_selling_orders.push(ad.material_ticker.clone());
                 continue;
             }
@@ -12,7 +12,7 @@ use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
 };
-use fiors::{get_material_db, types::Planet, FIOClient};
+use fiors::{get_material_db, types::Planet, FIOClient, HttpMode};
 use once_cell::sync::OnceCell;
 use ratatui::{prelude::*, widgets::*};
 use ratatui_macros::{horizontal, vertical};
@@ -30,11 +30,21 @@ fn get_client() -> &'static FIOClient {
     let api_key = std::env::args()
         .nth(1)
         .or(std::env::var("FIO_AUTH_TOKEN").ok())
-        .unwrap();
+        .unwrap_or_default();
     CLIENT.get_or_init(|| {
-        let client = FIOClient::new_with_key(api_key);
-        // client.local_cache_dir = Some(".fio_cache".into());
-        client
+        // FIO_RECORD_DIR records all responses, FIO_REPLAY_DIR serves them without network access
+        let http_mode = if let Ok(dir) = std::env::var("FIO_REPLAY_DIR") {
+            HttpMode::Replay(dir.into())
+        } else if let Ok(dir) = std::env::var("FIO_RECORD_DIR") {
+            HttpMode::Record(dir.into())
+        } else {
+            HttpMode::Live
+        };
+        // use .local_cache_dir(".fio_cache") to start from the disk cache
+        FIOClient::builder()
+            .http_mode(http_mode)
+            .build_with_key(api_key)
+            .expect("Failed to build FIO client")
     })
 }
 
@@ -171,10 +181,8 @@ fn format_price(price: f32) -> String {
         format!("{:.2}", price) // 9.12
     } else if price.abs() < 100.0 {
         format!("{:.1}", price) // 99.1
-    } else if price.abs() < 1000.0 {
-        format!("{:.0}", price) // 991
     } else if price.abs() < 10000.0 {
-        format!("{:.0}", price) // 9912
+        format!("{:.0}", price) // 991 or 9912
     } else {
         format!("{:.0}k", price / 1000.0)
     }
@@ -275,7 +283,7 @@ impl App {
             ],
             [Constraint::Length(3)],
         )
-        .highlight_style(Style::default().bg(Color::Blue));
+        .row_highlight_style(Style::default().bg(Color::Blue));
         match self.mode {
             SidebarMode::Production => self.sidebar_state.select(Some(0)),
             SidebarMode::Buildings => self.sidebar_state.select(Some(1)),
@@ -566,6 +574,9 @@ async fn run_mainloop(mut terminal: Terminal<impl Backend>, mut app: App) -> any
                 ..
             }) = event
             {
+                if let Err(e) = get_client().invalidate(fiors::CacheScope::All).await {
+                    tracing::error!("Failed to clear cache: {e}");
+                }
                 needs_redraw = needs_redraw.update(NeedRefresh::APIRefresh);
             }
             let (x, y) = app.handle_input(event);
@@ -621,12 +632,12 @@ async fn run_mainloop(mut terminal: Terminal<impl Backend>, mut app: App) -> any
                             ]
                             .as_ref(),
                         )
-                        .split(frame.size());
+                        .split(frame.area());
 
                     app.render_sidebar(frame, chunks[0]);
                     app.render_body(frame, chunks[1], &shared_state.help_text);
 
-                    let area = centered_rect(50, 20, frame.size());
+                    let area = centered_rect(50, 20, frame.area());
                     let para =
                         Paragraph::new("Loading...").block(Block::default().borders(Borders::ALL));
                     frame.render_widget(Clear, area);
@@ -655,7 +666,7 @@ async fn run_mainloop(mut terminal: Terminal<impl Backend>, mut app: App) -> any
             }
 
             // terminal.draw(|frame| {
-            //     let area = centered_rect(50, 20, frame.size());
+            //     let area = centered_rect(50, 20, frame.area());
             //     let para = Paragraph::new("Loading...")
             //     .block(Block::default().borders(Borders::ALL));
             //     frame.render_widget(Clear, area);
@@ -673,7 +684,7 @@ async fn run_mainloop(mut terminal: Terminal<impl Backend>, mut app: App) -> any
                         ]
                         .as_ref(),
                     )
-                    .split(frame.size());
+                    .split(frame.area());
 
                 app.render_sidebar(frame, chunks[0]);
                 app.render_body(frame, chunks[1], &shared_state.help_text);
@@ -126,7 +126,7 @@ fn handle_scroll(event: &Event, selected: Option<usize>, table_row_len: usize) -
     Some(new_i)
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Default)]
 pub enum OverrideType {
     /// Hold this much, even if our production needs are higher or lower
     Absolute(u32),
@@ -134,6 +134,7 @@ pub enum OverrideType {
     Minimum(u32),
     /// Hold exactly this much.  Any excess needs to be shipped off planet
     Maxiumum(u32),
+    #[default]
     None,
 }
 
@@ -156,12 +157,6 @@ impl OverrideType {
     }
 }
 
-impl Default for OverrideType {
-    fn default() -> Self {
-        Self::None
-    }
-}
-
 #[derive(Debug)]
 pub struct Overrides {
     /// How much of a given material we should keep in our inventory
@@ -174,6 +169,7 @@ pub struct Overrides {
     pub galactic_needs: HashMap<String, u32>,
 
     /// Override the price of a given material (for both market buys and own production)
+    #[allow(dead_code)]
     pub price_overrides: HashMap<String, f32>,
 
     /// When we last loaded these overrides from disk
@@ -21,10 +21,12 @@ use crate::{format_amount, format_price, get_style_for_material, NeedRefresh};
 use super::{handle_scroll, SharedWidgetState, WidgetEnum};
 
 #[derive(Clone)]
+#[allow(clippy::upper_case_acronyms)]
 enum COGM {
     Market {
         material: String,
         unit_cost: f32,
+        #[allow(dead_code)]
         total_cost: f32,
     },
     Ours {
@@ -169,7 +171,7 @@ impl BuildingsWidget {
 
             let building_cost = self
                 .client
-                .calc_building_cost(building.ticker, planet_cxid)
+                .calc_building_cost_on_planet(building.ticker, &self.planet_id, planet_cxid)
                 .await?;
             // assume we repair our buildings after 90 days
             let repair_cost = building_cost - (building_cost * 0.5).floor();
@@ -254,7 +256,7 @@ impl BuildingsWidget {
                         let units_per_cycle = (daily_amount_base / b).ceil();
                         let cycle_time = units_per_cycle / daily_amount_base / row.efficiency; // in days, taking into account efficiency
                                                                                                // we need this to calculate how much production fees we'll pay
-                        let cycles_per_day = 1.0 / cycle_time;
+                        let _cycles_per_day = 1.0 / cycle_time;
 
                         this_reciepe_row.push(Some({
                             let a = Span::raw(format!("{:>3}x", units_per_cycle));
@@ -501,7 +503,7 @@ impl BuildingsWidget {
             "This page shows all possible production receipes for each building at your base. ",
         )]);
 
-        if let Some(idx) = self.mat_idx_to_add.take() {}
+        if let Some(_idx) = self.mat_idx_to_add.take() {}
 
         Ok(())
     }
@@ -543,7 +545,7 @@ impl BuildingsWidget {
 
         let table = Table::new(self.rows.clone().into_iter().map(|x| x.row), widths)
             .header(Row::new(headers))
-            .highlight_style(Style::default().bg(Color::DarkGray))
+            .row_highlight_style(Style::default().bg(Color::DarkGray))
             .highlight_spacing(ratatui::widgets::HighlightSpacing::Always)
             .highlight_symbol(">")
             .block(
@@ -579,7 +581,7 @@ impl BuildingsWidget {
                 .input_mats
                 .iter()
                 .map(|mat| match mat {
-                    COGM::Market { material, unit_cost, total_cost } => Line::from(format!("{material}: {unit_cost}@market")),
+                    COGM::Market { material, unit_cost, total_cost: _ } => Line::from(format!("{material}: {unit_cost}@market")),
                     COGM::Ours {
                         material,
                         unit_cost,
@@ -1,4 +1,4 @@
-use fiors::types::{MarketOrder, Ticker};
+use fiors::types::{OrderBook, Ticker};
 use ratatui::{
     layout::Rect,
     style::Style,
@@ -7,32 +7,27 @@ use ratatui::{
     Frame,
 };
 
-use super::{SharedWidgetState, WidgetEnum};
+use super::WidgetEnum;
 
 #[derive(Default)]
 pub struct DepthChartWidget {
-    buy_orders: Vec<MarketOrder>,
-    sell_orders: Vec<MarketOrder>,
+    book: OrderBook,
     ticker: String,
+    /// Used to highlight our own orders
+    company_code: String,
 }
 
 impl DepthChartWidget {
-    pub fn update_with_ticker(&mut self, ticker: &Ticker) {
-        self.buy_orders = ticker.buying_orders.clone();
-        self.buy_orders.sort_by(|a, b| b.item_cost.partial_cmp(&a.item_cost).unwrap());
-
-        self.sell_orders = ticker.selling_orders.clone();
-        self.sell_orders.sort_by(|a, b| a.item_cost.partial_cmp(&b.item_cost).unwrap());
+    pub fn update_with_ticker(&mut self, ticker: &Ticker, company_code: &str) {
+        self.company_code = company_code.to_string();
+        self.book = ticker.order_book();
 
         self.ticker = ticker.name.to_string();
         tracing::trace!("Updated depth chart widget with ticker: {}", self.ticker);
     }
 
-    pub async fn update(&mut self, shared_state: &mut SharedWidgetState) -> anyhow::Result<()> {
-        Ok(())
-    }
-    pub fn render(&mut self, frame: &mut Frame, area: Rect, current_widget: WidgetEnum) {
-        let data: Vec<Bar> = self.calc(((area.width - 2) / 1) as usize, 0);
+    pub fn render(&mut self, frame: &mut Frame, area: Rect, _current_widget: WidgetEnum) {
+        let data: Vec<Bar> = self.calc((area.width - 2) as usize, 0);
 
         let bar = BarChart::default()
             .bar_gap(0)
@@ -40,13 +35,18 @@ impl DepthChartWidget {
             .data(BarGroup::default().bars(&data))
             .block(
                 Block::default()
-                    .title(format!("Depth Chart for {}", self.ticker))
+                    .title(match self.book.spread_percent() {
+                        Some(spread) => {
+                            format!("Depth Chart for {} (spread {spread:.1}%)", self.ticker)
+                        }
+                        None => format!("Depth Chart for {}", self.ticker),
+                    })
                     .borders(Borders::ALL),
             );
 
         frame.render_widget(bar, area);
     }
-    fn calc(&self, width: usize, selected_idx: usize) -> Vec<Bar> {
+    fn calc(&self, width: usize, _selected_idx: usize) -> Vec<Bar<'_>> {
         /*
 
 
@@ -58,18 +58,12 @@ impl DepthChartWidget {
         */
 
         // calculate price spread (stopping at market maker prices, if they exist)
-        let mut sell_orders = self
-            .sell_orders
-            .iter()
-            .take_while(|o| o.item_count.is_some());
+        let mut sell_orders = self.book.asks.iter().take_while(|o| o.item_count.is_some());
 
         let lowest_priced_sell = sell_orders.next().unwrap();
         let hightest_priced_sell = sell_orders.last().unwrap();
 
-        let mut buy_orders = self
-            .buy_orders
-            .iter()
-            .take_while(|o| o.item_count.is_some());
+        let mut buy_orders = self.book.bids.iter().take_while(|o| o.item_count.is_some());
 
         let highest_priced_buy = buy_orders.next().unwrap();
         let lowest_priced_buy = buy_orders.last().unwrap();
@@ -97,7 +91,8 @@ impl DepthChartWidget {
         tracing::trace!(width, half_width, sell_spread, sell_bucket_size);
 
         let buy_orders: Vec<_> = self
-            .buy_orders
+            .book
+            .bids
             .iter()
             .take_while(|o| o.item_count.is_some())
             .collect();
@@ -122,7 +117,7 @@ impl DepthChartWidget {
                     .sum::<u32>();
 
                 let our_order_in_window = buy_orders.iter().any(|o| {
-                    matches!(&o.company_code, Some(x) if x == "EM32")
+                    o.company_code.as_ref() == Some(&self.company_code)
                         && o.item_cost >= window_max
                         && o.item_cost < next_window_max
                 });
@@ -140,7 +135,8 @@ impl DepthChartWidget {
             .collect();
 
         let sell_orders: Vec<_> = self
-            .sell_orders
+            .book
+            .asks
             .iter()
             .take_while(|o| o.item_count.is_some())
             .collect();
@@ -164,7 +160,7 @@ impl DepthChartWidget {
 
                 // do we have any sell orders in this bucket?
                 let our_order_in_window = sell_orders.iter().any(|o| {
-                    matches!(&o.company_code, Some(x) if x == "EM32")
+                    o.company_code.as_ref() == Some(&self.company_code)
                         && o.item_cost <= window_max
                         && o.item_cost > prev_window_max
                 });
@@ -6,7 +6,7 @@ use ratatui::{layout::{Constraint, Rect}, style::{Color, Modifier, Style, Styliz
 
 use crate::{format_amount, NeedRefresh};
 
-use super::{production, SharedWidgetState, WidgetEnum};
+use super::{SharedWidgetState, WidgetEnum};
 
 enum MaterialState {
     /// A material that we need to acquire from the market
@@ -70,7 +70,7 @@ impl GalacticInvWidget {
             ])
             .add_modifier(Modifier::BOLD),
         )
-        .highlight_style(
+        .row_highlight_style(
             Style::default().fg(if current_widget == WidgetEnum::Global {
                 Color::Indexed(14)
             } else {
@@ -90,7 +90,7 @@ impl GalacticInvWidget {
     }
 
     /// Return true if we scrolled and need to redraw the widget
-    pub fn handle_input(&mut self, event: &Event, current_widget: WidgetEnum) -> NeedRefresh {
+    pub fn handle_input(&mut self, _event: &Event, _current_widget: WidgetEnum) -> NeedRefresh {
         NeedRefresh::No
     }
 
@@ -99,7 +99,7 @@ impl GalacticInvWidget {
 
         let mut material_map = HashMap::new();
         let mut needs = Vec::new();
-        /// A map from material to the planet(s) where it is produced
+        // A map from material to the planet(s) where it is produced
         let mut production_map: HashMap<String, HashSet<String>> = HashMap::new();
 
         let all_inv = self.client.get_all_storage_for_user(&self.username).await?;
@@ -114,7 +114,7 @@ impl GalacticInvWidget {
         let db = get_recipe_db();
 
         for (planet_id, planet_name) in &shared_state.planet_id_map {
-            let production_lines = self.client.get_planet_production(&self.username, &planet_id).await?;
+            let production_lines = self.client.get_planet_production(&self.username, planet_id).await?;
             let all_buildings = production_lines.into_iter().map(|pl| pl.building_ticker());
             for building_ticker in all_buildings {
                 for reciepe in db.iter().filter(|r| r.building_ticker == building_ticker) {
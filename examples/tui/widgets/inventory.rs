@@ -46,6 +46,7 @@ impl<T> Grid<T> {
 struct InvItem {
     name: String,
     amount: u32,
+    #[allow(dead_code)]
     value: f32,
     bg_color: Color,
     fg_color: Color,
@@ -131,6 +131,11 @@ impl LocalMarketWidget {
         let lm = self.client.get_planet_localmarket(&self.planet_id).await?;
         let planet = self.client.get_planet(&self.planet_id).await?;
         let planet_cxid = planet.get_cx_mid().unwrap_or("CI1");
+        let company_code = self
+            .client
+            .get_user_info(&self.username)
+            .await?
+            .company_code;
         // keep track of our own selling orders, we'll need this later
         let mut our_selling_orders = Vec::new();
         let mut notes = Vec::new();
@@ -142,7 +147,7 @@ impl LocalMarketWidget {
             let ticker_style = get_style_for_material(&ad.material_ticker);
 
             let mut symbol = " ";
-            if ad.creator_company_code == "EM32" {
+            if ad.creator_company_code == company_code {
                 our_selling_orders.push(ad.material_ticker.clone());
                 continue;
             }
@@ -321,7 +326,7 @@ impl LocalMarketWidget {
             ])
             .style(Style::default().add_modifier(Modifier::BOLD)),
         )
-        .highlight_style(
+        .row_highlight_style(
             Style::default().fg(if current_widget == WidgetEnum::LocalMarket {
                 Color::Indexed(14)
             } else {
@@ -1,19 +1,23 @@
 use anyhow::Context;
 use chrono::Utc;
 use crossterm::event::{Event, KeyCode, KeyEvent};
-use fiors::{get_material_db, FIOClient};
+use fiors::{
+    get_material_db,
+    types::{OrderStatus, OrderType},
+    FIOClient,
+};
 use ratatui::{
     layout::{Constraint, Margin, Rect},
     style::{Color, Modifier, Style, Stylize},
     text::Span,
     widgets::{
-        Block, Borders, Cell, Clear, Row, Scrollbar, ScrollbarOrientation, ScrollbarState, Table,
+        Block, Borders, Cell, Row, Scrollbar, ScrollbarOrientation, ScrollbarState, Table,
         TableState,
     },
     Frame,
 };
 
-use crate::{centered_rect, format_price, get_style_for_material, NeedRefresh};
+use crate::{format_price, get_style_for_material, NeedRefresh};
 
 use super::{handle_scroll, DepthChartWidget, SharedWidgetState, WidgetEnum};
 
@@ -85,7 +89,7 @@ impl MarketWidget {
         let now = Utc::now();
 
         for order in own_orders {
-            if order.status == "FILLED" {
+            if order.status == OrderStatus::Filled {
                 continue;
             }
 
@@ -94,9 +98,10 @@ impl MarketWidget {
                 .context("Material not found")?;
 
             let row = Row::new(vec![
-                Cell::from(match order.order_type.as_str() {
-                    "BUYING" => Span::styled("Buy", Style::default().fg(Color::Green)),
-                    _ => Span::styled("Sell", Style::default().fg(Color::Red)),
+                Cell::from(match order.order_type {
+                    OrderType::Buying => Span::styled("Buy", Style::default().fg(Color::Green)),
+                    OrderType::Selling => Span::styled("Sell", Style::default().fg(Color::Red)),
+                    OrderType::Other => Span::raw("?"),
                 }),
                 Cell::from(Span::styled(
                     order.material_ticker.to_string(),
@@ -123,11 +128,11 @@ impl MarketWidget {
                 ))),
                 Cell::from(Span::raw(order.limit_currency)),
                 Cell::from(Span::styled(
-                    match order.status.as_str() {
-                        "FILLED" => "Filled",
-                        "PLACED" => "Placed",
-                        "PARTIALLY_FILLED" => "Partially filled",
-                        _ => "Unknown",
+                    match order.status {
+                        OrderStatus::Filled => "Filled",
+                        OrderStatus::Placed => "Placed",
+                        OrderStatus::PartiallyFilled => "Partially filled",
+                        OrderStatus::Other => "Unknown",
                     },
                     Style::default().fg(Color::White),
                 )),
@@ -142,7 +147,7 @@ impl MarketWidget {
                 })),
             ]);
 
-            if order.order_type == "BUYING" {
+            if order.order_type == OrderType::Buying {
                 buy_rows.push(row);
             } else {
                 sell_rows.push(row);
@@ -152,14 +157,19 @@ impl MarketWidget {
         if self.want_depth_widget && self.depth_widget.is_none() {
             let mut dw = DepthChartWidget::default();
 
-            let idx = self.table_state.selected().unwrap_or(0);
+            let _idx = self.table_state.selected().unwrap_or(0);
             // let row = buy_rows.iter().chain(sell_rows.iter()).nth(idx).unwrap();
 
             let ticker = self
                 .client
                 .get_exchange_info(&format!("{}.{}", "SF", "CI1"))
                 .await?;
-            dw.update_with_ticker(&ticker);
+            let company_code = self
+                .client
+                .get_user_info(&self.username)
+                .await?
+                .company_code;
+            dw.update_with_ticker(&ticker, &company_code);
             self.depth_widget = Some(dw);
         } else if !self.want_depth_widget && self.depth_widget.is_some() {
             self.depth_widget = None;
@@ -216,7 +226,7 @@ impl MarketWidget {
             ])
             .add_modifier(Modifier::BOLD),
         )
-        .highlight_style(
+        .row_highlight_style(
             Style::default().fg(if current_widget == WidgetEnum::Market {
                 Color::Indexed(14)
             } else {
@@ -625,7 +625,7 @@ impl ProductionWidget {
             Row::new(vec!["", "Amt", "Mat", "Net", "COGM", "COGM", "CX Range"])
                 .style(Style::default().add_modifier(Modifier::BOLD)),
         )
-        .highlight_style(
+        .row_highlight_style(
             Style::default().fg(if current_widget == WidgetEnum::Production {
                 Color::Indexed(14)
             } else {
@@ -670,7 +670,7 @@ impl ProductionWidget {
         );
 
         let consumption_table = if current_widget == WidgetEnum::Consumption {
-            consumption_table.highlight_style(Style::default().fg(Color::Indexed(14)))
+            consumption_table.row_highlight_style(Style::default().fg(Color::Indexed(14)))
         } else {
             consumption_table
         };
@@ -683,7 +683,7 @@ impl ProductionWidget {
                 Constraint::Fill(1),
             ],
         )
-        .highlight_style(Style::default().fg(if current_widget == WidgetEnum::Needs {
+        .row_highlight_style(Style::default().fg(if current_widget == WidgetEnum::Needs {
             Color::Indexed(14)
         } else {
             Color::White
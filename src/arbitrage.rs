@@ -0,0 +1,433 @@
+//! Finding materials that can be bought on one exchange and sold on another for a profit
+//!
+//! [`scan`] compares the order books of every material across all exchanges of an
+//! [`ExchangeSnapshot`].  The quantity of an opportunity is limited by the order book depth: it's
+//! the number of units where the asks on one exchange are still cheaper than the bids on the
+//! other.  Prices on different exchanges are in different currencies, so they are converted with
+//! [`ExchangeRates`] first.
+
+use std::{cmp::Ordering, collections::HashMap};
+
+use crate::{
+    logistics::Cargo,
+    types::{ExchangeSnapshot, MarketOrder, Ticker},
+};
+
+/// Conversion rates between currencies, relative to a base currency
+///
+/// FIO doesn't publish exchange rates, so they have to be provided.
+///
+/// ```
+/// let rates = fiors::arbitrage::ExchangeRates::new("CIS")
+///     .with_rate("NCC", 1.1)
+///     .with_rate("ICA", 0.9);
+/// assert_eq!(rates.convert(10.0, "NCC"), Some(11.0));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ExchangeRates {
+    /// Like "CIS"
+    pub base: String,
+    /// Map from currency code to the value of one unit in the base currency
+    pub rates: HashMap<String, f32>,
+}
+
+impl ExchangeRates {
+    pub fn new(base: impl ToString) -> Self {
+        Self {
+            base: base.to_string(),
+            rates: HashMap::new(),
+        }
+    }
+
+    /// Sets the value of one unit of `currency` in the base currency
+    pub fn with_rate(mut self, currency: impl ToString, rate: f32) -> Self {
+        self.rates.insert(currency.to_string(), rate);
+        self
+    }
+
+    /// The value of one unit of `currency` in the base currency, if known
+    pub fn rate(&self, currency: &str) -> Option<f32> {
+        if currency == self.base {
+            Some(1.0)
+        } else {
+            self.rates.get(currency).copied()
+        }
+    }
+
+    /// Converts an amount of `currency` into the base currency
+    pub fn convert(&self, amount: f32, currency: &str) -> Option<f32> {
+        Some(amount * self.rate(currency)?)
+    }
+}
+
+impl Default for ExchangeRates {
+    fn default() -> Self {
+        Self::new("CIS")
+    }
+}
+
+/// Settings for [`scan`]
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    pub rates: ExchangeRates,
+    /// The most units to buy for one opportunity
+    pub max_quantity: u32,
+    /// Opportunities with a smaller gross margin (in the base currency, before shipping) are left
+    /// out
+    pub min_margin: f32,
+    /// Opportunities with a smaller [net profit](Opportunity::net_profit) (in the base currency)
+    /// are left out
+    ///
+    /// [`scan`] doesn't estimate shipping, so there this is the same as a minimum gross margin.
+    /// [`FIOClient::scan_arbitrage`](crate::FIOClient::scan_arbitrage) applies it again once the
+    /// shipping costs are known.
+    pub min_net_profit: f32,
+    /// Whether to trade with market makers
+    ///
+    /// Their stock is unlimited, so opportunities that involve them are capped by
+    /// [`max_quantity`](Self::max_quantity) only.
+    pub include_market_makers: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            rates: ExchangeRates::default(),
+            max_quantity: 1000,
+            min_margin: 0.0,
+            min_net_profit: 0.0,
+            include_market_makers: false,
+        }
+    }
+}
+
+/// Buying a material on one exchange and selling it on another, see [`scan`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Opportunity {
+    /// Material ticker, like "SF"
+    pub material: String,
+    /// Exchange code to buy at, like "CI1"
+    pub buy_exchange: String,
+    /// Exchange code to sell at, like "NC1"
+    pub sell_exchange: String,
+    pub buy_currency: String,
+    pub sell_currency: String,
+    pub quantity: u32,
+    /// The highest ask price we'd buy at, in the buy exchange's currency
+    pub buy_price_limit: f32,
+    /// The lowest bid price we'd sell at, in the sell exchange's currency
+    pub sell_price_limit: f32,
+    /// What buying costs, in the base currency
+    pub cost: f32,
+    /// What selling brings in, in the base currency
+    pub revenue: f32,
+    /// The size of the goods, or `None` if the material isn't in the material database
+    pub cargo: Option<Cargo>,
+    /// What it costs to ship the cargo between the exchanges, in the base currency, if it was
+    /// estimated (see [`FIOClient::scan_arbitrage`](crate::FIOClient::scan_arbitrage))
+    pub shipping_cost: Option<f32>,
+}
+
+impl Opportunity {
+    /// Revenue minus cost, before shipping
+    pub fn gross_margin(&self) -> f32 {
+        self.revenue - self.cost
+    }
+
+    /// The gross margin as a percentage of the cost
+    pub fn margin_percent(&self) -> f32 {
+        100.0 * self.gross_margin() / self.cost
+    }
+
+    /// The gross margin minus the shipping cost, if there is one
+    pub fn net_profit(&self) -> f32 {
+        self.gross_margin() - self.shipping_cost.unwrap_or_default()
+    }
+
+    /// The net profit per tonne or cubic meter of cargo, whichever the cargo has more of
+    ///
+    /// Ships fill up on weight or volume first, so this is what makes good use of a cargo hold.
+    /// Returns `None` if the cargo size is unknown.
+    pub fn profit_per_cargo(&self) -> Option<f32> {
+        let cargo = self.cargo?;
+        let size = cargo.weight.max(cargo.volume);
+        Some(if size > 0.0 {
+            self.net_profit() / size
+        } else {
+            self.net_profit()
+        })
+    }
+}
+
+/// Sorts opportunities by [`Opportunity::profit_per_cargo`], best first
+///
+/// Opportunities with an unknown cargo size come last, by net profit.
+pub fn rank(opportunities: &mut [Opportunity]) {
+    opportunities.sort_by(|a, b| match (a.profit_per_cargo(), b.profit_per_cargo()) {
+        (Some(a), Some(b)) => b.total_cmp(&a),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => b.net_profit().total_cmp(&a.net_profit()),
+    });
+}
+
+/// Finds every material that can be bought on one exchange and sold on another for more
+///
+/// Exchanges whose currency has no rate in [`ScanOptions::rates`] are skipped.  The result is
+/// [`rank`]ed, and doesn't include shipping costs.
+pub fn scan(snapshot: &ExchangeSnapshot, options: &ScanOptions) -> Vec<Opportunity> {
+    let mut by_material: HashMap<&str, Vec<(&str, &Ticker)>> = HashMap::new();
+    for ticker in snapshot.tickers.values() {
+        if let Some((material, exchange)) = ticker.name.split_once('.') {
+            by_material
+                .entry(material)
+                .or_default()
+                .push((exchange, ticker));
+        }
+    }
+
+    let mut opportunities = Vec::new();
+    for (material, tickers) in by_material {
+        for &(buy_exchange, buy) in &tickers {
+            for &(sell_exchange, sell) in &tickers {
+                if buy_exchange == sell_exchange {
+                    continue;
+                }
+                if let Some(opportunity) = find(
+                    material,
+                    (buy_exchange, buy),
+                    (sell_exchange, sell),
+                    options,
+                ) {
+                    opportunities.push(opportunity);
+                }
+            }
+        }
+    }
+    rank(&mut opportunities);
+    opportunities
+}
+
+/// Walks the asks of `buy` and the bids of `sell` for as long as buying is cheaper than selling
+fn find(
+    material: &str,
+    (buy_exchange, buy): (&str, &Ticker),
+    (sell_exchange, sell): (&str, &Ticker),
+    options: &ScanOptions,
+) -> Option<Opportunity> {
+    let buy_rate = options.rates.rate(&buy.currency)?;
+    let sell_rate = options.rates.rate(&sell.currency)?;
+    let levels = |orders: &[MarketOrder]| -> Vec<(f32, u32)> {
+        orders
+            .iter()
+            .filter_map(|o| match o.item_count {
+                Some(count) => Some((o.item_cost, count)),
+                None if options.include_market_makers => Some((o.item_cost, options.max_quantity)),
+                None => None,
+            })
+            .collect()
+    };
+    let mut asks = levels(&buy.order_book().asks);
+    let mut bids = levels(&sell.order_book().bids);
+
+    let (mut i, mut j) = (0, 0);
+    let mut quantity = 0;
+    let (mut cost, mut revenue) = (0.0, 0.0);
+    let (mut buy_price_limit, mut sell_price_limit) = (0.0, 0.0);
+    while i < asks.len() && j < bids.len() && quantity < options.max_quantity {
+        let (ask, ask_count) = &mut asks[i];
+        let (bid, bid_count) = &mut bids[j];
+        if *ask * buy_rate >= *bid * sell_rate {
+            break;
+        }
+        let amount = (*ask_count)
+            .min(*bid_count)
+            .min(options.max_quantity - quantity);
+        quantity += amount;
+        cost += *ask * buy_rate * amount as f32;
+        revenue += *bid * sell_rate * amount as f32;
+        buy_price_limit = *ask;
+        sell_price_limit = *bid;
+        *ask_count -= amount;
+        *bid_count -= amount;
+        if *ask_count == 0 {
+            i += 1;
+        }
+        if *bid_count == 0 {
+            j += 1;
+        }
+    }
+
+    let margin = revenue - cost;
+    if quantity == 0 || margin < options.min_margin || margin < options.min_net_profit {
+        return None;
+    }
+    let cargo = Cargo::from_materials([(material, quantity)]).ok();
+    Some(Opportunity {
+        material: material.to_string(),
+        buy_exchange: buy_exchange.to_string(),
+        sell_exchange: sell_exchange.to_string(),
+        buy_currency: buy.currency.clone(),
+        sell_currency: sell.currency.clone(),
+        quantity,
+        buy_price_limit,
+        sell_price_limit,
+        cost,
+        revenue,
+        cargo,
+        shipping_cost: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    use crate::logistics::ShipClass;
+
+    fn ticker(name: &str, currency: &str, bids: &[(f32, u32)], asks: &[(f32, u32)]) -> Ticker {
+        let (material, exchange) = name.split_once('.').unwrap();
+        let orders = |orders: &[(f32, u32)]| {
+            orders
+                .iter()
+                .map(|(cost, count)| {
+                    serde_json::json!({
+                        "CompanyCode": "ABC",
+                        "CompanyName": "Somebody",
+                        "ItemCost": cost,
+                        "ItemCount": count,
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        serde_json::from_value(serde_json::json!({
+            "MaterialTicker": material,
+            "ExchangeCode": exchange,
+            "Currency": currency,
+            "Price": null,
+            "Ask": null,
+            "Bid": null,
+            "High": null,
+            "Low": null,
+            "Supply": 0,
+            "Demand": 0,
+            "Traded": 0,
+            "MMBuy": null,
+            "MMSell": null,
+            "BuyingOrders": orders(bids),
+            "SellingOrders": orders(asks),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_scan() {
+        let tickers = [
+            ticker("H2O.CI1", "CIS", &[(40.0, 100)], &[(50.0, 30), (60.0, 100)]),
+            // worth 2 CIS per NCC, so the bids are 140 and 110 CIS
+            ticker("H2O.NC1", "NCC", &[(70.0, 10), (55.0, 100)], &[]),
+            ticker("RAT.CI1", "CIS", &[], &[(100.0, 10)]),
+            ticker("RAT.NC1", "NCC", &[(80.0, 10)], &[]),
+            // no rate for AIC
+            ticker("RAT.AI1", "AIC", &[(1000.0, 10)], &[]),
+            // not in the material database
+            ticker("XYZ.CI1", "CIS", &[], &[(1.0, 10)]),
+            ticker("XYZ.NC1", "NCC", &[(1000.0, 10)], &[]),
+        ];
+        let snapshot = ExchangeSnapshot {
+            tickers: tickers.into_iter().map(|t| (t.name.clone(), t)).collect(),
+            rejected: Vec::new(),
+            fetched_at: Utc::now(),
+        };
+        let options = ScanOptions {
+            rates: ExchangeRates::new("CIS").with_rate("NCC", 2.0),
+            ..Default::default()
+        };
+
+        let found = scan(&snapshot, &options);
+        assert_eq!(found.len(), 3);
+        let h2o = found.iter().find(|o| o.material == "H2O").unwrap();
+        assert_eq!(
+            (h2o.buy_exchange.as_str(), h2o.sell_exchange.as_str()),
+            ("CI1", "NC1")
+        );
+        // 10 @ 50 -> 140, 20 @ 50 -> 110, 80 @ 60 -> 110
+        assert_eq!(h2o.quantity, 110);
+        assert_eq!(h2o.cost, 30.0 * 50.0 + 80.0 * 60.0);
+        assert_eq!(h2o.revenue, 10.0 * 140.0 + 100.0 * 110.0);
+        assert_eq!((h2o.buy_price_limit, h2o.sell_price_limit), (60.0, 55.0));
+        assert!((h2o.cargo.unwrap().weight - 22.0).abs() < 1e-3);
+
+        let rat = found.iter().find(|o| o.material == "RAT").unwrap();
+        assert_eq!(rat.gross_margin(), 600.0);
+        assert_eq!(rat.margin_percent(), 60.0);
+        // 600 for 2.1 t of RAT beats 6100 for 22 t of H2O
+        assert_eq!(found[0].material, "RAT");
+        // a huge margin, but no idea how much room it takes
+        assert_eq!(found[2].material, "XYZ");
+        assert_eq!(found[2].cargo, None);
+        assert_eq!(found[2].profit_per_cargo(), None);
+
+        let capped = scan(
+            &snapshot,
+            &ScanOptions {
+                max_quantity: 5,
+                min_margin: 400.0,
+                ..options
+            },
+        );
+        assert_eq!(capped.len(), 2);
+        assert_eq!(capped[0].material, "H2O");
+        assert_eq!(capped[0].quantity, 5);
+    }
+
+    #[tokio::test]
+    async fn test_scan_arbitrage() {
+        let (_server, client) = crate::testing::mock_client().await.unwrap();
+        let found = client
+            .scan_arbitrage(&ScanOptions::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].material, "RAT");
+        assert_eq!(
+            (
+                found[0].buy_exchange.as_str(),
+                found[0].sell_exchange.as_str()
+            ),
+            ("CI1", "CI2")
+        );
+        assert_eq!(found[0].quantity, 1000);
+        assert_eq!(found[0].gross_margin(), 1000.0 * (90.0 - 40.0));
+        assert_eq!(found[0].shipping_cost, None);
+
+        // 210 t of RAT on a standard ship: 97 SF for takeoff and landing, 7 FF for 10 parsecs
+        let found = client
+            .scan_arbitrage(&ScanOptions::default(), Some(&ShipClass::STANDARD))
+            .await
+            .unwrap();
+        assert_eq!(found[0].shipping_cost, Some(97.0 * 10.0 + 7.0 * 20.0));
+        assert_eq!(found[0].net_profit(), 50000.0 - 1110.0);
+
+        // the gross margin is enough, but not once the fuel is paid for
+        let options = ScanOptions {
+            min_margin: 49000.0,
+            ..Default::default()
+        };
+        let found = client
+            .scan_arbitrage(&options, Some(&ShipClass::STANDARD))
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        let options = ScanOptions {
+            min_net_profit: 49000.0,
+            ..Default::default()
+        };
+        let found = client
+            .scan_arbitrage(&options, Some(&ShipClass::STANDARD))
+            .await
+            .unwrap();
+        assert!(found.is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+use crate::{retry, Error, Result, RetryPolicy};
+
+/// Username and password kept by a [`FIOClient`](crate::FIOClient) so it can log in again when its
+/// auth token expires
+#[derive(Clone)]
+pub(crate) struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct AuthState {
+    pub token: String,
+    /// When the token expires.  API keys don't expire, so this is `None` for them
+    pub expiry: Option<DateTime<Utc>>,
+}
+
+/// Posts to `/auth/login` and returns the new auth token and its expiry
+pub(crate) async fn login(
+    client: &reqwest::Client,
+    base_url: &str,
+    retry_policy: &RetryPolicy,
+    credentials: &Credentials,
+) -> Result<AuthState> {
+    let url = "/auth/login";
+    let post_data =
+        serde_json::json!({"UserName": credentials.username, "Password": credentials.password});
+
+    let resp = retry::send_with_retry(retry_policy, url, || {
+        client
+            .post(format!("{base_url}{url}"))
+            .json(&post_data)
+            .header("Accept", "application/json")
+    })
+    .await?;
+
+    if resp.status.as_u16() == 401 {
+        return Err(Error::Auth);
+    } else if !resp.status.is_success() {
+        return Err(Error::Http {
+            url: url.to_string(),
+            status: resp.status,
+        });
+    }
+
+    let mut resp_body: HashMap<String, serde_json::Value> =
+        serde_json::from_slice(&resp.body).map_err(Error::decode(url))?;
+
+    let decode_err = |msg: &str| Error::decode(url)(anyhow::anyhow!("{msg}"));
+    let token = resp_body
+        .remove("AuthToken")
+        .ok_or_else(|| decode_err("Missing AuthToken from login response"))?
+        .as_str()
+        .ok_or_else(|| decode_err("Unexpected type for AuthToken"))?
+        .to_string();
+    let expiry = resp_body
+        .remove("Expiry")
+        .ok_or_else(|| decode_err("Missing Expiry from login response"))?;
+
+    let expiry = chrono::DateTime::parse_from_rfc3339(
+        expiry
+            .as_str()
+            .ok_or_else(|| decode_err("Unexpected type for expiry"))?,
+    )
+    .map_err(Error::decode(url))?
+    .to_utc();
+
+    Ok(AuthState {
+        token,
+        expiry: Some(expiry),
+    })
+}
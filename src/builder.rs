@@ -0,0 +1,230 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::auth::{self, AuthState, Credentials};
+use crate::types::PriceBasis;
+use crate::{CacheConfig, Endpoint, Error, FIOClient, HttpMode, Result, RetryPolicy};
+
+/// The public FIO REST endpoint
+pub const DEFAULT_BASE_URL: &str = "https://rest.fnar.net";
+
+/// Configures and constructs a [`FIOClient`]
+///
+/// ```no_run
+/// # async fn example() -> fiors::Result<()> {
+/// use std::time::Duration;
+///
+/// let client = fiors::FIOClientBuilder::new()
+///     .base_url("http://localhost:8080")
+///     .timeout(Duration::from_secs(30))
+///     .user_agent("my-tool/1.0")
+///     .local_cache_dir(".fio_cache")
+///     .build_with_key("my-api-key")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct FIOClientBuilder {
+    pub(crate) base_url: String,
+    timeout: Duration,
+    user_agent: Option<String>,
+    proxy: Option<reqwest::Proxy>,
+    pub(crate) cache: CacheConfig,
+    pub(crate) retry_policy: RetryPolicy,
+    keep_credentials: bool,
+    pub(crate) http_mode: HttpMode,
+    pub(crate) refresh_margin: Duration,
+    pub(crate) price_basis: PriceBasis,
+}
+
+impl Default for FIOClientBuilder {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            timeout: Duration::from_secs(100),
+            user_agent: None,
+            proxy: None,
+            cache: CacheConfig::default(),
+            retry_policy: RetryPolicy::default(),
+            keep_credentials: false,
+            http_mode: HttpMode::Live,
+            refresh_margin: Duration::from_secs(5 * 60),
+            price_basis: PriceBasis::Spot,
+        }
+    }
+}
+
+impl FIOClientBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The root URL of the FIO REST API, without a trailing slash
+    ///
+    /// Defaults to [`DEFAULT_BASE_URL`].
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// How long to wait for a single request before it's considered timed out
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    pub fn proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Directory where raw responses are cached on disk
+    pub fn local_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache.dir = Some(dir.into());
+        self
+    }
+
+    /// Overrides how long data from one endpoint is cached, see [`Endpoint::default_ttl`]
+    pub fn cache_ttl(mut self, endpoint: Endpoint, ttl: Duration) -> Self {
+        self.cache.ttls.insert(endpoint, ttl);
+        self
+    }
+
+    /// Limits the total size of the on-disk cache
+    pub fn max_disk_cache_bytes(mut self, max: u64) -> Self {
+        self.cache.max_disk_bytes = Some(max);
+        self
+    }
+
+    /// Limits the number of entries in each in-memory cache
+    pub fn max_memory_cache_entries(mut self, max: usize) -> Self {
+        self.cache.max_memory_entries = max;
+        self
+    }
+
+    /// Replaces all cache settings at once
+    pub fn cache_config(mut self, config: CacheConfig) -> Self {
+        self.cache = config;
+        self
+    }
+
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Record responses to fixtures, or replay them without touching the network
+    ///
+    /// When replaying, [`build_with_password`](Self::build_with_password) doesn't log in either.
+    /// Recording can't be combined with [`local_cache_dir`](Self::local_cache_dir), since responses
+    /// served from the disk cache would never make it into a fixture.
+    pub fn http_mode(mut self, mode: HttpMode) -> Self {
+        self.http_mode = mode;
+        self
+    }
+
+    /// Keep the username and password after a password login, so the client can log in again
+    /// when its auth token expires
+    ///
+    /// Off by default, since it means holding the password in memory for the lifetime of the client.
+    pub fn keep_credentials(mut self, keep: bool) -> Self {
+        self.keep_credentials = keep;
+        self
+    }
+
+    /// How long before the auth token expires the client logs in again
+    ///
+    /// Only has an effect together with [`keep_credentials`](Self::keep_credentials).  Defaults to 5 minutes.
+    pub fn refresh_before_expiry(mut self, margin: Duration) -> Self {
+        self.refresh_margin = margin;
+        self
+    }
+
+    /// Which exchange price cost calculations like
+    /// [`calc_cost_of_goods_manufactured`](FIOClient::calc_cost_of_goods_manufactured) use
+    ///
+    /// Defaults to [`PriceBasis::Spot`].
+    pub fn price_basis(mut self, basis: PriceBasis) -> Self {
+        self.price_basis = basis;
+        self
+    }
+
+    fn build_http_client(&self) -> Result<reqwest::Client> {
+        if matches!(self.http_mode, HttpMode::Record(_)) && self.cache.dir.is_some() {
+            return Err(Error::Config(
+                "Recording responses doesn't work with a local cache dir".into(),
+            ));
+        }
+        let mut builder = reqwest::ClientBuilder::new().timeout(self.timeout);
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        builder
+            .build()
+            .map_err(|e| Error::Config(format!("Failed to build HTTP client: {e}")))
+    }
+
+    /// Builds a client that authenticates with an API key
+    pub fn build_with_key(self, auth_token: impl Into<String>) -> Result<FIOClient> {
+        let client = self.build_http_client()?;
+        let auth = AuthState {
+            token: auth_token.into(),
+            expiry: None,
+        };
+        Ok(FIOClient::from_parts(self, client, auth, None))
+    }
+
+    /// Logs in with a username and password and builds a client using the returned auth token
+    ///
+    /// If [`keep_credentials`](Self::keep_credentials) is set, the client logs in again whenever the
+    /// token is about to expire or gets rejected.
+    pub async fn build_with_password(self, username: &str, password: &str) -> Result<FIOClient> {
+        let client = self.build_http_client()?;
+        if let HttpMode::Replay(_) = self.http_mode {
+            let auth = AuthState {
+                token: String::new(),
+                expiry: None,
+            };
+            return Ok(FIOClient::from_parts(self, client, auth, None));
+        }
+        let credentials = Credentials {
+            username: username.to_string(),
+            password: password.to_string(),
+        };
+        let auth = auth::login(&client, &self.base_url, &self.retry_policy, &credentials).await?;
+        let credentials = self.keep_credentials.then_some(credentials);
+        Ok(FIOClient::from_parts(self, client, auth, credentials))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let client = FIOClientBuilder::new()
+            .base_url("http://localhost:1234/")
+            .local_cache_dir("/tmp/fio")
+            .build_with_key("key")
+            .unwrap();
+
+        assert_eq!(client.base_url(), "http://localhost:1234");
+        assert_eq!(client.local_cache_dir(), Some("/tmp/fio".as_ref()));
+        assert_eq!(client.token_expiry(), None);
+        assert_eq!(client.token_time_remaining(), None);
+
+        let recording = FIOClientBuilder::new()
+            .local_cache_dir("/tmp/fio")
+            .http_mode(HttpMode::Record("/tmp/fio_fixtures".into()))
+            .build_with_key("key");
+        assert!(matches!(recording, Err(Error::Config(_))));
+    }
+}
@@ -0,0 +1,640 @@
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    hash::Hash,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{debug, trace};
+
+use crate::Result;
+
+/// Groups of FIO endpoints that share the same freshness rules
+///
+/// Every cached response, in memory or on disk, is considered fresh for the TTL of its endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    /// `/planet/...` and `/infrastructure/...`
+    Planet,
+    /// `/sites/...` and `/storage/planets/...`
+    Sites,
+    /// `/storage/...`
+    Storage,
+    /// `/workforce/...`
+    Workforce,
+    /// `/localmarket/...`
+    LocalMarket,
+    /// `/exchange/...`
+    Exchange,
+    /// `/production/...`
+    Production,
+    /// `/cxos/...`
+    Orders,
+    /// `/ship/...`
+    Ships,
+    /// `/contract/...`
+    Contracts,
+    /// `/user/...` and `/company/...`
+    Profile,
+    /// `/material/...`, `/building/...`, `/recipes/...` and `/systemstars`
+    Catalog,
+    /// Anything else
+    Other,
+}
+
+impl Endpoint {
+    /// Figures out which endpoint a request URL (like `/planet/UV-351a`) belongs to
+    pub fn from_url(url: &str) -> Self {
+        let url = url.trim_start_matches('/');
+        if url.starts_with("storage/planets/") {
+            return Self::Sites;
+        }
+        match url.split('/').next().unwrap_or_default() {
+            "planet" | "infrastructure" => Self::Planet,
+            "sites" => Self::Sites,
+            "storage" => Self::Storage,
+            "workforce" => Self::Workforce,
+            "localmarket" => Self::LocalMarket,
+            "exchange" => Self::Exchange,
+            "production" => Self::Production,
+            "cxos" => Self::Orders,
+            "ship" => Self::Ships,
+            "contract" => Self::Contracts,
+            "user" | "company" => Self::Profile,
+            "material" | "building" | "recipes" | "systemstars" => Self::Catalog,
+            _ => Self::Other,
+        }
+    }
+
+    /// How long data from this endpoint is cached unless configured otherwise
+    pub fn default_ttl(self) -> Duration {
+        let secs = match self {
+            Self::Planet | Self::Sites | Self::Catalog => 24 * 3600,
+            Self::Profile | Self::Other => 3600,
+            Self::Workforce => 2 * 3600,
+            Self::Storage | Self::Exchange | Self::Production | Self::Orders | Self::Contracts => {
+                15 * 60
+            }
+            Self::LocalMarket | Self::Ships => 10 * 60,
+        };
+        Duration::from_secs(secs)
+    }
+}
+
+/// Settings for the client's cache
+///
+/// Responses are always cached in memory.  If `dir` is set, raw responses are also written to disk,
+/// so a new client can start from them as long as they are still fresh.
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// Directory for the on-disk cache
+    pub dir: Option<PathBuf>,
+    /// Per-endpoint TTLs that override [`Endpoint::default_ttl`]
+    pub ttls: HashMap<Endpoint, Duration>,
+    /// Maximum number of entries in each in-memory cache.  The oldest entries are evicted first
+    pub max_memory_entries: usize,
+    /// Maximum total size of the on-disk cache.  The oldest files are deleted first
+    pub max_disk_bytes: Option<u64>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            ttls: HashMap::new(),
+            max_memory_entries: 10_000,
+            max_disk_bytes: None,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn ttl(&self, endpoint: Endpoint) -> Duration {
+        self.ttls
+            .get(&endpoint)
+            .copied()
+            .unwrap_or_else(|| endpoint.default_ttl())
+    }
+}
+
+/// Which cached data to drop in [`FIOClient::invalidate`](crate::FIOClient::invalidate)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheScope {
+    /// A single ticker, like `"RAT.CI1"`
+    ///
+    /// Since all tickers are fetched together in `/exchange/full`, this drops all exchange data,
+    /// just like [`CacheScope::Exchange`].
+    Ticker(String),
+    /// All exchange data
+    Exchange,
+    /// All storage (including warehouses) of one user, and the list of planets they have storage on
+    UserStorage(String),
+    /// One store of one user, as passed to
+    /// [`get_storage_for_user`](crate::FIOClient::get_storage_for_user)
+    ///
+    /// This also drops the user's combined storage list, which includes the store.
+    Store { user: String, store: String },
+    /// Production lines of one user on one planet
+    PlanetProduction { user: String, planet: String },
+    /// Everything, in memory and on disk
+    All,
+}
+
+/// When and from where a cached response was fetched
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct EntryMeta {
+    pub url: String,
+    pub fetched_at: DateTime<Utc>,
+    pub ttl: Duration,
+    /// The cache generation the fetch started in, see [`Cache::invalidate`]
+    #[serde(skip)]
+    pub generation: u64,
+}
+
+impl EntryMeta {
+    pub fn is_fresh(&self) -> bool {
+        self.fetched_at + self.ttl > Utc::now()
+    }
+}
+
+/// A response together with its fetch metadata
+#[derive(Clone)]
+pub(crate) struct Fetched<T> {
+    pub data: T,
+    pub meta: EntryMeta,
+}
+
+#[derive(Deserialize)]
+struct DiskEntry<T> {
+    meta: EntryMeta,
+    data: T,
+}
+
+#[derive(Serialize)]
+struct DiskEntryRef<'a, T> {
+    meta: &'a EntryMeta,
+    data: &'a T,
+}
+
+/// The on-disk tier of the cache, plus the TTL rules shared with the in-memory tier
+#[derive(Debug)]
+pub(crate) struct Cache {
+    config: CacheConfig,
+    /// Serializes disk size enforcement
+    disk_lock: tokio::sync::Mutex<()>,
+    /// Bumped on every invalidation, shared with the in-memory caches
+    generation: Arc<AtomicU64>,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            disk_lock: tokio::sync::Mutex::new(()),
+            generation: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    /// Metadata for a response to `url` fetched right now
+    pub fn meta_for(&self, url: &str) -> EntryMeta {
+        EntryMeta {
+            url: url.to_string(),
+            fetched_at: Utc::now(),
+            ttl: self.config.ttl(Endpoint::from_url(url)),
+            generation: self.generation.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Starts an invalidation
+    ///
+    /// Responses whose fetch started before this are still returned to their callers, but no
+    /// longer cached, so they can't overwrite what the invalidation is about to drop.  Call this
+    /// before removing anything.
+    pub fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn is_current(&self, meta: &EntryMeta) -> bool {
+        meta.generation == self.generation.load(Ordering::SeqCst)
+    }
+
+    fn path(&self, url: &str) -> Option<PathBuf> {
+        self.config
+            .dir
+            .as_deref()
+            .map(|dir| dir.join(file_name(url)))
+    }
+
+    /// Loads a fresh response from disk
+    ///
+    /// Freshness is checked against the currently configured TTL, not the one the entry was written with.
+    pub async fn load<T: DeserializeOwned>(&self, url: &str) -> Option<Fetched<T>> {
+        let body = tokio::fs::read(self.path(url)?).await.ok()?;
+        trace!("Trying to load data from local disk cache");
+        let entry: DiskEntry<T> = serde_json::from_slice(&body).ok()?;
+        if entry.meta.url != url {
+            debug!(stored = %entry.meta.url, "Disk cache entry belongs to another URL");
+            return None;
+        }
+        let meta = EntryMeta {
+            ttl: self.config.ttl(Endpoint::from_url(url)),
+            generation: self.generation.load(Ordering::SeqCst),
+            ..entry.meta
+        };
+        meta.is_fresh().then_some(Fetched {
+            data: entry.data,
+            meta,
+        })
+    }
+
+    /// Writes a response to disk, if a cache directory is configured
+    ///
+    /// Responses fetched before the last invalidation are skipped.
+    pub async fn store<T: Serialize>(&self, data: &T, meta: &EntryMeta) -> Result<()> {
+        let Some(path) = self.path(&meta.url) else {
+            return Ok(());
+        };
+        if !self.is_current(meta) {
+            debug!(url = %meta.url, "Not caching response that was invalidated during its fetch");
+            return Ok(());
+        }
+        let body = serde_json::to_vec(&DiskEntryRef { meta, data })
+            .map_err(|e| crate::Error::Io(e.into()))?;
+        if let Some(p) = path.parent() {
+            tokio::fs::create_dir_all(p).await?;
+        }
+        tokio::fs::write(&path, body).await?;
+        if !self.is_current(meta) {
+            // an invalidation ran while we were writing and might have missed the file
+            tokio::fs::remove_file(path).await?;
+            return Ok(());
+        }
+
+        if let (Some(dir), Some(max)) = (&self.config.dir, self.config.max_disk_bytes) {
+            let _guard = self.disk_lock.lock().await;
+            let dir = dir.clone();
+            tokio::task::spawn_blocking(move || enforce_disk_limit(&dir, max))
+                .await
+                .map_err(|e| crate::Error::Io(std::io::Error::other(e)))??;
+        }
+        Ok(())
+    }
+
+    /// Removes the cached response for `url` from disk
+    pub async fn remove(&self, url: &str) -> Result<()> {
+        self.remove_prefix(url, false).await
+    }
+
+    /// Removes the cached responses for `url` and every URL below it, like `/storage/user/...`
+    pub async fn remove_tree(&self, url: &str) -> Result<()> {
+        self.remove_prefix(url, true).await
+    }
+
+    async fn remove_prefix(&self, url: &str, subpaths: bool) -> Result<()> {
+        let (Some(dir), Some(path)) = (&self.config.dir, self.path(url)) else {
+            return Ok(());
+        };
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        // file names are escaped, so this only matches URLs that continue with a `/`
+        let below = format!("{stem}_");
+        let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+            return Ok(());
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let Some(name) = path.file_stem().map(|s| s.to_string_lossy()) else {
+                continue;
+            };
+            let matches = name == stem || (subpaths && name.starts_with(&below));
+            if matches && path.extension().is_some_and(|ext| ext == "json") {
+                tokio::fs::remove_file(path).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes every response from disk
+    pub async fn clear(&self) -> Result<()> {
+        let Some(dir) = &self.config.dir else {
+            return Ok(());
+        };
+        match tokio::fs::read_dir(dir).await {
+            Ok(mut entries) => {
+                while let Some(entry) = entries.next_entry().await? {
+                    let path = entry.path();
+                    if path.extension().is_some_and(|ext| ext == "json") {
+                        tokio::fs::remove_file(path).await?;
+                    }
+                }
+                Ok(())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// The name of the file a response to `url` is stored in, like `planet_UV-351a.json`
+///
+/// Every `/` becomes `_`, and anything that isn't a letter, digit, `-` or `.` (including `_`
+/// itself) is percent-escaped, so different URLs never share a file.
+pub(crate) fn file_name(url: &str) -> String {
+    let mut name = String::with_capacity(url.len() + 5);
+    for b in url.strip_prefix('/').unwrap_or(url).bytes() {
+        match b {
+            b'/' => name.push('_'),
+            b if b.is_ascii_alphanumeric() || b == b'-' || b == b'.' => name.push(b as char),
+            b => name.push_str(&format!("%{b:02X}")),
+        }
+    }
+    name.push_str(".json");
+    name
+}
+
+/// Deletes the least recently written cache files until the directory is at most `max_bytes`
+///
+/// This walks the whole directory, so it runs on a blocking thread.
+fn enforce_disk_limit(dir: &Path, max_bytes: u64) -> Result<()> {
+    let mut files = Vec::new();
+    let mut total = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let md = entry.metadata()?;
+        if !md.is_file() || entry.path().extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        total += md.len();
+        files.push((md.modified()?, md.len(), entry.path()));
+    }
+
+    files.sort();
+    for (_, len, path) in files {
+        if total <= max_bytes {
+            break;
+        }
+        debug!(?path, "Evicting from disk cache");
+        std::fs::remove_file(path)?;
+        total -= len;
+    }
+    Ok(())
+}
+
+struct CachedData<T> {
+    data: T,
+    meta: EntryMeta,
+}
+
+/// The in-memory tier of the cache, holding parsed data
+pub(crate) struct MemoryCache<K, T> {
+    map: DashMap<K, CachedData<T>>,
+    max_entries: usize,
+    generation: Arc<AtomicU64>,
+}
+
+impl<K: Hash + Eq + Clone, T: Clone> MemoryCache<K, T> {
+    pub fn new(cache: &Cache) -> Self {
+        Self {
+            map: DashMap::new(),
+            max_entries: cache.config.max_memory_entries,
+            generation: cache.generation.clone(),
+        }
+    }
+
+    /// Returns the cached data if it's still fresh
+    pub fn get<Q>(&self, key: &Q) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let cached = self.map.get(key)?;
+        cached.meta.is_fresh().then(|| cached.data.clone())
+    }
+
+    /// Caches `data`, unless it was fetched before the last invalidation
+    pub fn insert(&self, key: K, data: T, meta: &EntryMeta) {
+        if meta.generation != self.generation.load(Ordering::SeqCst) {
+            return;
+        }
+        self.map.insert(
+            key.clone(),
+            CachedData {
+                data,
+                meta: meta.clone(),
+            },
+        );
+        if meta.generation != self.generation.load(Ordering::SeqCst) {
+            // an invalidation ran in between and might have missed our entry
+            self.map
+                .remove_if(&key, |_, cached| cached.meta.generation == meta.generation);
+            return;
+        }
+        if self.map.len() > self.max_entries {
+            self.evict();
+        }
+    }
+
+    pub fn remove<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.remove(key);
+    }
+
+    /// Drops every entry whose key doesn't satisfy `keep`
+    pub fn retain(&self, keep: impl Fn(&K) -> bool) {
+        self.map.retain(|key, _| keep(key));
+    }
+
+    pub fn clear(&self) {
+        self.map.clear();
+    }
+
+    /// Drops stale entries, then the oldest ones until we're a tenth below the size limit
+    ///
+    /// Making room for more than one entry means the map isn't sorted again on every insert.
+    fn evict(&self) {
+        self.map.retain(|_, cached| cached.meta.is_fresh());
+        let target = self.max_entries - self.max_entries / 10;
+        if self.map.len() <= self.max_entries {
+            return;
+        }
+        let excess = self.map.len() - target;
+        let mut by_age: Vec<(DateTime<Utc>, K)> = self
+            .map
+            .iter()
+            .map(|e| (e.meta.fetched_at, e.key().clone()))
+            .collect();
+        by_age.sort_by_key(|(fetched_at, _)| *fetched_at);
+        for (_, key) in by_age.into_iter().take(excess) {
+            self.map.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_ttl() {
+        assert_eq!(Endpoint::from_url("/planet/UV-351a"), Endpoint::Planet);
+        assert_eq!(Endpoint::from_url("/storage/planets/user"), Endpoint::Sites);
+        assert_eq!(Endpoint::from_url("/storage/user"), Endpoint::Storage);
+        assert_eq!(Endpoint::from_url("/exchange/full"), Endpoint::Exchange);
+        assert_eq!(Endpoint::from_url("/ship/flights/user"), Endpoint::Ships);
+        assert_eq!(Endpoint::from_url("/auth"), Endpoint::Other);
+
+        let mut config = CacheConfig::default();
+        assert_eq!(config.ttl(Endpoint::LocalMarket), Duration::from_secs(600));
+        config
+            .ttls
+            .insert(Endpoint::LocalMarket, Duration::from_secs(5));
+        assert_eq!(config.ttl(Endpoint::LocalMarket), Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_disk_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("fiors_cache_test_{}", std::process::id()));
+        let cache = Cache::new(CacheConfig {
+            dir: Some(dir.clone()),
+            max_disk_bytes: Some(1_000_000),
+            ..Default::default()
+        });
+
+        let meta = cache.meta_for("/planet/UV-351a");
+        cache.store(&vec![1, 2, 3], &meta).await.unwrap();
+        let loaded: Fetched<Vec<i32>> = cache.load("/planet/UV-351a").await.unwrap();
+        assert_eq!(loaded.data, vec![1, 2, 3]);
+        assert_eq!(loaded.meta.fetched_at, meta.fetched_at);
+
+        // stale entries are not returned
+        let old = EntryMeta {
+            fetched_at: Utc::now() - Duration::from_secs(7200),
+            ..cache.meta_for("/auth")
+        };
+        cache.store(&1, &old).await.unwrap();
+        assert!(cache.load::<i32>("/auth").await.is_none());
+
+        let meta = cache.meta_for("/storage/user");
+        cache.store(&1, &meta).await.unwrap();
+        let meta = cache.meta_for("/storage/user/UV-351a");
+        cache.store(&2, &meta).await.unwrap();
+        let meta = cache.meta_for("/storage/user2");
+        cache.store(&3, &meta).await.unwrap();
+        let meta = cache.meta_for("/storage/user_x");
+        cache.store(&4, &meta).await.unwrap();
+        let meta = cache.meta_for("/storage/user_x/UV-351a");
+        cache.store(&5, &meta).await.unwrap();
+        cache.remove_tree("/storage/user").await.unwrap();
+        assert!(cache.load::<i32>("/storage/user").await.is_none());
+        assert!(cache.load::<i32>("/storage/user/UV-351a").await.is_none());
+        assert!(cache.load::<i32>("/storage/user2").await.is_some());
+        assert!(cache.load::<i32>("/storage/user_x").await.is_some());
+        assert!(cache.load::<i32>("/storage/user_x/UV-351a").await.is_some());
+
+        // URLs that only differ in separators get their own files
+        for (i, url) in [
+            "/exchange/RAT.CI1",
+            "/exchange/RAT_CI1",
+            "/exchange/RAT/CI1",
+        ]
+        .iter()
+        .enumerate()
+        {
+            cache.store(&i, &cache.meta_for(url)).await.unwrap();
+        }
+        assert_eq!(
+            cache.load::<usize>("/exchange/RAT_CI1").await.unwrap().data,
+            1
+        );
+        assert_eq!(
+            cache.load::<usize>("/exchange/RAT/CI1").await.unwrap().data,
+            2
+        );
+
+        // an entry written for another URL is never served
+        let path = dir.join(file_name("/planet/UV-351b"));
+        std::fs::copy(dir.join(file_name("/planet/UV-351a")), &path).unwrap();
+        assert!(cache.load::<Vec<i32>>("/planet/UV-351b").await.is_none());
+
+        enforce_disk_limit(&dir, 0).unwrap();
+        assert!(cache.load::<Vec<i32>>("/planet/UV-351a").await.is_none());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_memory_eviction() {
+        let cache = MemoryCache::new(&Cache::new(CacheConfig {
+            max_memory_entries: 2,
+            ..Default::default()
+        }));
+        let meta = |secs_ago| EntryMeta {
+            url: String::new(),
+            fetched_at: Utc::now() - Duration::from_secs(secs_ago),
+            ttl: Duration::from_secs(3600),
+            generation: 0,
+        };
+        cache.insert("a".to_string(), 1, &meta(30));
+        cache.insert("b".to_string(), 2, &meta(20));
+        cache.insert("c".to_string(), 3, &meta(10));
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), Some(2));
+        assert_eq!(cache.get("c"), Some(3));
+
+        cache.insert("d".to_string(), 4, &meta(7200));
+        assert_eq!(cache.get("d"), None);
+
+        // going over the limit makes room for a few more entries at once
+        let cache = MemoryCache::new(&Cache::new(CacheConfig {
+            max_memory_entries: 20,
+            ..Default::default()
+        }));
+        for i in 0..21 {
+            cache.insert(i, i, &meta(100 - i as u64));
+        }
+        assert_eq!(cache.map.len(), 18);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&3), Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_during_fetch() {
+        let dir = std::env::temp_dir().join(format!("fiors_gen_test_{}", std::process::id()));
+        let cache = Cache::new(CacheConfig {
+            dir: Some(dir.clone()),
+            ..Default::default()
+        });
+        let memory = MemoryCache::new(&cache);
+
+        // the fetch starts, then the data gets invalidated before it finishes
+        let meta = cache.meta_for("/storage/user");
+        cache.invalidate();
+        memory.insert("user".to_string(), 1, &meta);
+        cache.store(&1, &meta).await.unwrap();
+        assert_eq!(memory.get("user"), None);
+        assert!(cache.load::<i32>("/storage/user").await.is_none());
+
+        // the next fetch is cached as usual, also when it came from disk
+        let meta = cache.meta_for("/storage/user");
+        memory.insert("user".to_string(), 2, &meta);
+        cache.store(&2, &meta).await.unwrap();
+        assert_eq!(memory.get("user"), Some(2));
+        let loaded = cache.load::<i32>("/storage/user").await.unwrap();
+        memory.insert("user".to_string(), loaded.data, &loaded.meta);
+        assert_eq!(memory.get("user"), Some(2));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
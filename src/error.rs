@@ -0,0 +1,112 @@
+use std::{fmt, sync::Arc};
+
+use reqwest::StatusCode;
+
+/// Errors returned by [`FIOClient`](crate::FIOClient)
+#[derive(Debug)]
+pub enum Error {
+    /// FIO rejected the auth token or the login credentials (HTTP 401)
+    Auth,
+    /// FIO has no data for the requested resource (HTTP 204 or 404)
+    NotFound { url: String },
+    /// FIO answered with an unexpected HTTP status
+    Http { url: String, status: StatusCode },
+    /// The response body could not be decoded into the expected type
+    Decode {
+        url: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// The request did not complete within the configured timeout
+    Timeout { url: String },
+    /// FIO kept asking us to back off until we ran out of retries
+    RetriesExhausted { url: String, attempts: u32 },
+    /// Any other error from the HTTP client (connection refused, DNS, TLS, ...)
+    Request(reqwest::Error),
+    /// Reading or writing local files failed
+    Io(std::io::Error),
+    /// The client could not be configured (missing auth token, invalid HTTP client settings, ...)
+    Config(String),
+    /// Data needed for a calculation is missing, like an unknown building or a ticker without a price
+    MissingData(String),
+    /// Another caller was fetching the same data at the same time, and its request failed with this
+    Shared(Arc<Error>),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// Wraps a decoding error together with the URL of the response that failed to decode
+    pub(crate) fn decode<E>(url: &str) -> impl FnOnce(E) -> Self + '_
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        move |e| Self::Decode {
+            url: url.to_string(),
+            source: e.into(),
+        }
+    }
+
+    pub(crate) fn from_request(url: &str, e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::Timeout {
+                url: url.to_string(),
+            }
+        } else {
+            Self::Request(e)
+        }
+    }
+
+    /// Returns true if this is a [`Error::NotFound`]
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Self::NotFound { .. } => true,
+            Self::Shared(e) => e.is_not_found(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auth => write!(f, "Failed to authenticate"),
+            Self::NotFound { url } => write!(f, "No data found for {url}"),
+            Self::Http { url, status } => write!(f, "Request to {url} not successful: {status}"),
+            Self::Decode { url, .. } => write!(f, "Failed JSON decoding {url}"),
+            Self::Timeout { url } => write!(f, "Request to {url} timed out"),
+            Self::RetriesExhausted { url, attempts } => {
+                write!(f, "Request to {url} failed after {attempts} attempts")
+            }
+            Self::Request(e) => write!(f, "HTTP request failed: {e}"),
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::Config(msg) => write!(f, "Invalid client configuration: {msg}"),
+            Self::MissingData(msg) => write!(f, "Missing data: {msg}"),
+            Self::Shared(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode { source, .. } => Some(source.as_ref()),
+            Self::Request(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::Shared(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        let url = e.url().map(|u| u.to_string()).unwrap_or_default();
+        Self::from_request(&url, e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
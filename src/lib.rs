@@ -1,16 +1,8 @@
-use std::{
-    collections::HashMap,
-    fs::File,
-    path::{Path, PathBuf},
-    sync::atomic::AtomicU64,
-    time::Duration,
-};
-
-use anyhow::{bail, Context};
+use std::{collections::HashMap, path::Path, sync::Arc};
+
 use chrono::Utc;
-use dashmap::DashMap;
 use serde::{de::DeserializeOwned, Serialize};
-use serde_json::Value;
+use serde_json::{value::RawValue, Value};
 
 mod material_db;
 pub use material_db::get_material_db;
@@ -20,55 +12,97 @@ pub use building_db::get_building_db;
 
 mod recipe_db;
 pub use recipe_db::get_recipe_db;
-use tracing::{instrument, trace, warn};
+
+mod builder;
+pub use builder::{FIOClientBuilder, DEFAULT_BASE_URL};
+
+mod retry;
+pub use retry::RetryPolicy;
+
+mod auth;
+use auth::{AuthState, Credentials};
+
+mod cache;
+use cache::{Cache, Fetched, MemoryCache};
+pub use cache::{CacheConfig, CacheScope, Endpoint};
+
+mod recording;
+pub use recording::HttpMode;
+use recording::RawResponse;
+
+mod single_flight;
+use single_flight::{KeyedLocks, SingleFlight};
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+mod error;
+pub use error::{Error, Result};
+use tracing::{debug, instrument, trace, warn};
 use types::WarehouseInfo;
 
 use crate::types::{Item, WorkforceDetails};
 
+pub mod arbitrage;
+pub mod logistics;
 pub mod materials;
+pub mod starmap;
 pub mod types;
 
-struct CachedData<T> {
-    data: T,
-    expiry: chrono::DateTime<Utc>,
-}
-
-impl<T: Clone> CachedData<T> {
-    fn new(data: T, expiry: std::time::Duration) -> Self {
-        Self {
-            data,
-            expiry: Utc::now() + expiry,
-        }
-    }
-}
-
 pub struct FIOClient {
-    url_root: &'static str,
-    auth_token: String,
-    expiry: Option<chrono::DateTime<Utc>>,
+    url_root: String,
+    auth: std::sync::RwLock<AuthState>,
+    /// Only set for password logins with [`FIOClientBuilder::keep_credentials`]
+    credentials: Option<Credentials>,
+    /// Makes sure only one request logs in again when the token expires
+    login_lock: tokio::sync::Mutex<()>,
+    refresh_margin: std::time::Duration,
+    price_basis: types::PriceBasis,
     client: reqwest::Client,
-    pub local_cache_dir: Option<PathBuf>,
-    retry_delay: AtomicU64,
-
-    planet_cache: DashMap<String, CachedData<types::Planet>>,
+    retry_policy: RetryPolicy,
+    http_mode: HttpMode,
+    /// The on-disk cache and the TTLs shared by all caches
+    cache: Cache,
+    /// Downloads that are currently running, shared by concurrent requests for the same URL
+    in_flight: SingleFlight<Fetched<Option<Arc<[u8]>>>>,
+    /// Held while fetching and parsing data for a typed cache, so it's only done once
+    flights: KeyedLocks,
+
+    planet_cache: MemoryCache<String, types::Planet>,
+    infrastructure_cache: MemoryCache<String, types::PlanetInfrastructure>,
 
     /// Map from (username, storage_id) to a list of all stores (including warehouses)
-    storage_user_cache: DashMap<String, CachedData<Vec<types::Storage>>>,
+    storage_user_cache: MemoryCache<String, Vec<types::Storage>>,
 
     /// Map from (username, storage_id) to storage info (base storage only)
-    storage_cache: DashMap<(String, String), CachedData<Option<types::Storage>>>,
+    storage_cache: MemoryCache<(String, String), Option<types::Storage>>,
 
     /// Map from username to list of storage info.
     ///
     /// The WarehouseInfo struct doesn't contain the actual storage data, but rather the storage ID and the type of storage
-    warehouse_cache: DashMap<String, CachedData<Vec<types::WarehouseInfo>>>,
-
-    planet_info_cache: DashMap<String, CachedData<Vec<types::Planet>>>,
-    workforce_cache: DashMap<(String, String), CachedData<types::PlanetWorkforce>>,
-    localmarket_cache: DashMap<String, CachedData<types::LocalMarket>>,
-    exchange_cache: DashMap<String, CachedData<types::Ticker>>,
-    planet_production_cache: DashMap<(String, String), CachedData<Vec<types::ProductionLine>>>,
-    own_orders_cache: DashMap<String, CachedData<Vec<types::OwnMarketOrder>>>,
+    warehouse_cache: MemoryCache<String, Vec<types::WarehouseInfo>>,
+
+    planet_info_cache: MemoryCache<String, Vec<types::Planet>>,
+    site_planets_cache: MemoryCache<String, Vec<types::SiteSummary>>,
+    sites_cache: MemoryCache<String, Vec<types::PlanetSite>>,
+    workforce_cache: MemoryCache<(String, String), types::PlanetWorkforce>,
+    localmarket_cache: MemoryCache<String, types::LocalMarket>,
+    /// Keyed by URL, although there's only `/exchange/full`
+    exchange_cache: MemoryCache<String, Arc<types::ExchangeSnapshot>>,
+    planet_production_cache: MemoryCache<(String, String), Vec<types::ProductionLine>>,
+    own_orders_cache: MemoryCache<String, Vec<types::OwnMarketOrder>>,
+    ships_cache: MemoryCache<String, Vec<types::Ship>>,
+    flights_cache: MemoryCache<String, Vec<types::Flight>>,
+    contracts_cache: MemoryCache<String, Vec<types::Contract>>,
+    /// Keyed by URL, since profiles can be looked up by username or company code
+    profile_cache: MemoryCache<String, types::CompanyProfile>,
+    /// Map from full ticker name to its price history
+    price_history_cache: MemoryCache<String, Arc<types::PriceHistory>>,
+    materials_cache: MemoryCache<String, Arc<types::Catalog<HashMap<String, types::MaterialInfo>>>>,
+    buildings_cache: MemoryCache<String, Arc<types::Catalog<HashMap<String, types::BuildingInfo>>>>,
+    recipes_cache: MemoryCache<String, Arc<types::Catalog<Vec<types::Recipe>>>>,
+    all_planets_cache: MemoryCache<String, Arc<types::Catalog<Vec<types::Planet>>>>,
+    star_map_cache: MemoryCache<String, Arc<starmap::StarMap>>,
 }
 
 #[derive(Debug, Clone)]
@@ -118,390 +152,527 @@ impl COGMSource {
 }
 
 impl FIOClient {
-    pub async fn new_with_password(username: String, password: String) -> anyhow::Result<Self> {
-        // first post a login and extract the authtoken and expiry
-
-        let post_data = serde_json::json!({"UserName": username, "Password": password});
-        let client = reqwest::ClientBuilder::new()
-            .timeout(Duration::from_secs(100))
-            .build()?;
-        let url_root = "https://rest.fnar.net";
-
-        let req = client
-            .post(format!("{url_root}/auth/login"))
-            .json(&post_data)
-            .header("Accept", "application/json")
-            .build()?;
-
-        let resp = client.execute(req).await?;
-
-        if resp.status().as_u16() == 401 {
-            bail!("Failed to authenticate")
-        }
-
-        let mut resp_body: HashMap<String, serde_json::Value> = resp.json().await?;
-
-        let auth_token = resp_body
-            .remove("AuthToken")
-            .context("Missing AuthToken from login response")?
-            .as_str()
-            .context("Unexpected type for AuthToken")?
-            .to_string();
-        let expiry = resp_body
-            .remove("Expiry")
-            .context("Missing Expiry from login response")?;
-
-        let expiry = chrono::DateTime::parse_from_rfc3339(
-            expiry.as_str().context("Unexpected type for expiry")?,
-        )?
-        .to_utc();
-
-        Ok(Self {
-            url_root,
-            auth_token,
-            expiry: Some(expiry),
-            client,
-            local_cache_dir: None,
-            retry_delay: AtomicU64::new(500),
-            planet_cache: DashMap::new(),
-            storage_user_cache: DashMap::new(),
-            storage_cache: DashMap::new(),
-            planet_info_cache: DashMap::new(),
-            warehouse_cache: DashMap::new(),
-            workforce_cache: DashMap::new(),
-            localmarket_cache: DashMap::new(),
-            exchange_cache: DashMap::new(),
-            planet_production_cache: DashMap::new(),
-            own_orders_cache: DashMap::new(),
-        })
+    pub fn builder() -> FIOClientBuilder {
+        FIOClientBuilder::new()
     }
 
-    pub fn new_with_key(auth_token: String) -> Self {
-        let client = reqwest::ClientBuilder::new()
-            .timeout(Duration::from_secs(100))
-            .build()
-            .unwrap();
-        let url_root = "https://rest.fnar.net";
+    pub async fn new_with_password(username: String, password: String) -> Result<Self> {
+        FIOClientBuilder::new()
+            .build_with_password(&username, &password)
+            .await
+    }
+
+    /// Fails only if the HTTP client can't be set up, see [`FIOClientBuilder::build_with_key`]
+    pub fn new_with_key(auth_token: String) -> Result<Self> {
+        FIOClientBuilder::new().build_with_key(auth_token)
+    }
+
+    pub fn new_from_env() -> Result<Self> {
+        let auth_token = std::env::var("FIO_AUTH_TOKEN")
+            .map_err(|_| Error::Config("FIO_AUTH_TOKEN is not set".into()))?;
+        Self::new_with_key(auth_token)
+    }
+
+    pub(crate) fn from_parts(
+        builder: FIOClientBuilder,
+        client: reqwest::Client,
+        auth: AuthState,
+        credentials: Option<Credentials>,
+    ) -> Self {
+        let cache = Cache::new(builder.cache);
         Self {
-            url_root,
-            auth_token,
-            expiry: None,
+            url_root: builder.base_url,
+            auth: std::sync::RwLock::new(auth),
+            credentials,
+            login_lock: tokio::sync::Mutex::new(()),
+            refresh_margin: builder.refresh_margin,
+            price_basis: builder.price_basis,
             client,
-            local_cache_dir: None,
-            retry_delay: AtomicU64::new(500),
-            planet_cache: DashMap::new(),
-            storage_user_cache: DashMap::new(),
-            storage_cache: DashMap::new(),
-            planet_info_cache: DashMap::new(),
-            warehouse_cache: DashMap::new(),
-            workforce_cache: DashMap::new(),
-            localmarket_cache: DashMap::new(),
-            exchange_cache: DashMap::new(),
-            planet_production_cache: DashMap::new(),
-            own_orders_cache: DashMap::new(),
+            retry_policy: builder.retry_policy,
+            http_mode: builder.http_mode,
+            planet_cache: MemoryCache::new(&cache),
+            infrastructure_cache: MemoryCache::new(&cache),
+            storage_user_cache: MemoryCache::new(&cache),
+            storage_cache: MemoryCache::new(&cache),
+            planet_info_cache: MemoryCache::new(&cache),
+            site_planets_cache: MemoryCache::new(&cache),
+            sites_cache: MemoryCache::new(&cache),
+            warehouse_cache: MemoryCache::new(&cache),
+            workforce_cache: MemoryCache::new(&cache),
+            localmarket_cache: MemoryCache::new(&cache),
+            exchange_cache: MemoryCache::new(&cache),
+            planet_production_cache: MemoryCache::new(&cache),
+            own_orders_cache: MemoryCache::new(&cache),
+            ships_cache: MemoryCache::new(&cache),
+            flights_cache: MemoryCache::new(&cache),
+            contracts_cache: MemoryCache::new(&cache),
+            profile_cache: MemoryCache::new(&cache),
+            price_history_cache: MemoryCache::new(&cache),
+            materials_cache: MemoryCache::new(&cache),
+            buildings_cache: MemoryCache::new(&cache),
+            recipes_cache: MemoryCache::new(&cache),
+            all_planets_cache: MemoryCache::new(&cache),
+            star_map_cache: MemoryCache::new(&cache),
+            cache,
+            in_flight: SingleFlight::new(),
+            flights: KeyedLocks::new(),
         }
     }
 
-    pub fn new_from_env() -> anyhow::Result<Self> {
-        let auth_token = std::env::var("FIO_AUTH_TOKEN")?;
-        Ok(Self::new_with_key(auth_token))
+    /// Directory of the on-disk cache, if enabled
+    pub fn local_cache_dir(&self) -> Option<&Path> {
+        self.cache.config().dir.as_deref()
     }
 
-    fn should_retry(&self) -> bool {
-        // self.retry_delay.load(std::sync::atomic::Ordering::Relaxed) < 15000
-        true
+    /// Drops cached data, in memory and on disk, so the next call fetches it from FIO again
+    pub async fn invalidate(&self, scope: CacheScope) -> Result<()> {
+        // fetches that are running right now must not cache what we're about to drop
+        self.cache.invalidate();
+        match scope {
+            CacheScope::Ticker(ticker) => {
+                self.exchange_cache.clear();
+                self.price_history_cache.remove(&ticker);
+                self.cache.remove("/exchange/full").await?;
+                self.cache
+                    .remove(&format!("/exchange/cxpc/{ticker}"))
+                    .await?;
+            }
+            CacheScope::Exchange => {
+                self.exchange_cache.clear();
+                self.price_history_cache.clear();
+                self.cache.remove_tree("/exchange").await?;
+            }
+            CacheScope::UserStorage(user) => {
+                self.storage_user_cache.remove(&user);
+                self.storage_cache.retain(|(u, _)| *u != user);
+                self.warehouse_cache.remove(&user);
+                self.planet_info_cache.remove(&user);
+                self.cache.remove_tree(&format!("/storage/{user}")).await?;
+                self.cache
+                    .remove(&format!("/storage/planets/{user}"))
+                    .await?;
+                self.cache
+                    .remove(&format!("/sites/warehouses/{user}"))
+                    .await?;
+            }
+            CacheScope::Store { user, store } => {
+                self.storage_cache.remove(&(user.clone(), store.clone()));
+                self.storage_user_cache.remove(&user);
+                self.cache
+                    .remove(&format!("/storage/{user}/{store}"))
+                    .await?;
+                self.cache.remove(&format!("/storage/{user}")).await?;
+            }
+            CacheScope::PlanetProduction { user, planet } => {
+                self.planet_production_cache
+                    .remove(&(user.clone(), planet.clone()));
+                self.cache
+                    .remove(&format!("/production/{user}/{planet}"))
+                    .await?;
+            }
+            CacheScope::All => {
+                self.planet_cache.clear();
+                self.infrastructure_cache.clear();
+                self.storage_user_cache.clear();
+                self.storage_cache.clear();
+                self.warehouse_cache.clear();
+                self.planet_info_cache.clear();
+                self.site_planets_cache.clear();
+                self.sites_cache.clear();
+                self.workforce_cache.clear();
+                self.localmarket_cache.clear();
+                self.exchange_cache.clear();
+                self.planet_production_cache.clear();
+                self.own_orders_cache.clear();
+                self.ships_cache.clear();
+                self.flights_cache.clear();
+                self.contracts_cache.clear();
+                self.profile_cache.clear();
+                self.price_history_cache.clear();
+                self.materials_cache.clear();
+                self.buildings_cache.clear();
+                self.recipes_cache.clear();
+                self.all_planets_cache.clear();
+                self.star_map_cache.clear();
+                self.cache.clear().await?;
+            }
+        }
+        Ok(())
     }
 
-    async fn retry_sleep(&self) {
-        let time_ms = self.retry_delay.load(std::sync::atomic::Ordering::Relaxed);
-        tokio::time::sleep(Duration::from_millis(time_ms)).await;
+    /// The root URL that all requests are sent to
+    pub fn base_url(&self) -> &str {
+        &self.url_root
     }
-    fn increase_retry(&self) {
-        let time_ms = self.retry_delay.load(std::sync::atomic::Ordering::Relaxed);
-        self.retry_delay.store(
-            (time_ms as f32 * 1.75).min(15000.0) as u64,
-            std::sync::atomic::Ordering::Relaxed,
-        );
-        // println!("Retry delay now {:?}", self.retry_delay);
+
+    /// When the current auth token expires
+    ///
+    /// Returns `None` for API keys, which don't expire.
+    pub fn token_expiry(&self) -> Option<chrono::DateTime<Utc>> {
+        self.auth.read().unwrap().expiry
     }
-    fn decrease_retry(&self) {
-        let time_ms = self.retry_delay.load(std::sync::atomic::Ordering::Relaxed);
 
-        self.retry_delay.store(
-            time_ms.saturating_sub(500).max(500),
-            std::sync::atomic::Ordering::Relaxed,
-        );
-        // println!("Retry delay now {:?}", self.retry_delay);
+    /// How long the current auth token remains valid, or `None` for API keys
+    pub fn token_time_remaining(&self) -> Option<chrono::Duration> {
+        self.token_expiry()
+            .map(|expiry| (expiry - Utc::now()).max(chrono::Duration::zero()))
+    }
+
+    fn auth_token(&self) -> String {
+        self.auth.read().unwrap().token.clone()
+    }
+
+    /// Logs in again, unless another request already replaced `stale_token` in the meantime
+    async fn relogin(&self, stale_token: &str) -> Result<()> {
+        let Some(credentials) = &self.credentials else {
+            return Err(Error::Auth);
+        };
+        let _guard = self.login_lock.lock().await;
+        if self.auth.read().unwrap().token != stale_token {
+            return Ok(());
+        }
+        debug!("Logging in again to refresh auth token");
+        let auth = auth::login(
+            &self.client,
+            &self.url_root,
+            &self.retry_policy,
+            credentials,
+        )
+        .await?;
+        *self.auth.write().unwrap() = auth;
+        Ok(())
     }
 
+    /// Sends an authorized GET request
+    ///
+    /// If the client kept its credentials, it logs in again shortly before the token expires, and
+    /// retries once if FIO rejects the token anyway.
+    async fn send_authorized(&self, url: &str, accept: &str) -> Result<RawResponse> {
+        let mut token = self.auth_token();
+        if self.credentials.is_some() {
+            let expiring = self
+                .token_expiry()
+                .is_some_and(|expiry| expiry - self.refresh_margin <= Utc::now());
+            if expiring {
+                self.relogin(&token).await?;
+                token = self.auth_token();
+            }
+        }
+
+        let send = |token: String| {
+            retry::send_with_retry(&self.retry_policy, url, move || {
+                self.client
+                    .get(format!("{}{url}", self.url_root))
+                    .header("Authorization", &token)
+                    .header("accept", accept)
+            })
+        };
+
+        let resp = send(token.clone()).await?;
+        if resp.status.as_u16() == 401 && self.credentials.is_some() {
+            warn!("Auth token was rejected, logging in again");
+            self.relogin(&token).await?;
+            return send(self.auth_token()).await;
+        }
+        Ok(resp)
+    }
+
+    /// Sends a GET request, or loads a fresh response from the disk cache
+    ///
+    /// Returns `None` in the data if FIO answered with 204 No Content.
     #[instrument(skip(self))]
     async fn request<T: DeserializeOwned + Serialize>(
         &self,
         url: &str,
-    ) -> anyhow::Result<Option<T>> {
-        let get_cache = |path: &Path| {
-            path.join(url.trim_matches('/').replace(['/', '.'], "_"))
-                .with_extension("json")
-        };
-
-        if let Some(cache) = self
-            .local_cache_dir
-            .as_deref()
-            .map(get_cache)
-            .and_then(|path| File::open(path).ok())
-        {
-            let md = cache
-                .metadata()
-                .ok()
-                .and_then(|md| md.modified().ok())
-                .and_then(|md| md.elapsed().ok())
-                .map(|d| d.as_secs() < 3600)
-                .unwrap_or(false);
-
-            trace!("Trying to load data from local disk cache");
-            if md {
-                if let Ok(loaded_from_cache) = serde_json::from_reader(cache) {
-                    // println!("Returning from cache");
-                    return Ok(Some(loaded_from_cache));
-                }
-            }
+    ) -> Result<Fetched<Option<T>>> {
+        if let Some(cached) = self.cache.load(url).await {
+            return Ok(Fetched {
+                data: Some(cached.data),
+                meta: cached.meta,
+            });
         }
 
-        while self.should_retry() {
-            let resp = self
-                .client
-                .get(format!("{}{url}", self.url_root))
-                .header("Authorization", &self.auth_token)
-                .header("accept", "application/json")
-                .send()
-                .await;
+        // concurrent callers share one download
+        let (fetched, leader) = self.in_flight.run(url, || self.fetch_body(url)).await?;
 
-            if matches!(resp, Err(ref e) if e.is_timeout()) {
-                warn!("Request timed out, retrying");
-                self.retry_sleep().await;
-                self.increase_retry();
-                continue;
+        let Some(body) = fetched.data else {
+            return Ok(Fetched {
+                data: None,
+                meta: fetched.meta,
+            });
+        };
+        let data: T = serde_json::from_slice(&body).map_err(Error::decode(url))?;
+        if leader {
+            if let Err(e) = self.cache.store(&data, &fetched.meta).await {
+                warn!("Failed to write disk cache: {e}");
             }
+        }
+        Ok(Fetched {
+            data: Some(data),
+            meta: fetched.meta,
+        })
+    }
 
-            let resp = resp?;
+    /// Downloads the raw body of a GET request, or `None` for 204 No Content
+    async fn fetch_body(&self, url: &str) -> Result<Fetched<Option<Arc<[u8]>>>> {
+        let resp = self.get_raw(url, "application/json").await?;
+        let meta = self.cache.meta_for(url);
+
+        if resp.status.as_u16() == 204 {
+            Ok(Fetched { data: None, meta })
+        } else if resp.status.is_success() {
+            Ok(Fetched {
+                data: Some(resp.body.into()),
+                meta,
+            })
+        } else {
+            Err(Self::status_error(url, resp.status))
+        }
+    }
 
-            let status = resp.status();
-            if status.as_u16() == 522 || status.as_u16() == 525 {
-                warn!("Got a 522/525, retrying");
-            }
+    /// Sends a GET request, recording or replaying it depending on the [`HttpMode`]
+    async fn get_raw(&self, url: &str, accept: &str) -> Result<RawResponse> {
+        if let HttpMode::Replay(dir) = &self.http_mode {
+            trace!("Replaying recorded response");
+            return recording::replay(dir, url);
+        }
 
-            if status.as_u16() == 429 || status.as_u16() == 522 || status.as_u16() == 525 {
-                self.retry_sleep().await;
-                self.increase_retry();
-                continue;
-            }
-            self.decrease_retry();
-
-            if status.as_u16() == 204 {
-                return Ok(None);
-            } else if status.is_success() {
-                let data: T = resp
-                    .json()
-                    .await
-                    .with_context(|| format!("Failed JSON decoding {url}"))?;
-                if let Some(cache) = self
-                    .local_cache_dir
-                    .as_deref()
-                    .map(get_cache)
-                    .inspect(|path| {
-                        if let Some(p) = path.parent() {
-                            let _ = std::fs::create_dir_all(p);
-                        }
-                    })
-                    .and_then(|path| File::create(path).ok())
-                {
-                    serde_json::to_writer(cache, &data)?;
-                }
-                return Ok(Some(data));
-            } else {
-                bail!("Request not successful: {:?}", status);
-            }
+        let resp = self.send_authorized(url, accept).await?;
+        if let HttpMode::Record(dir) = &self.http_mode {
+            recording::record(dir, url, &resp)?;
         }
+        Ok(resp)
+    }
 
-        bail!("Failed after too many retries")
+    fn status_error(url: &str, status: reqwest::StatusCode) -> Error {
+        match status.as_u16() {
+            401 => Error::Auth,
+            404 => Error::NotFound {
+                url: url.to_string(),
+            },
+            _ => Error::Http {
+                url: url.to_string(),
+                status,
+            },
+        }
     }
 
     /// Returns the username of the logged in user
-    pub async fn is_auth(&self) -> anyhow::Result<String> {
-        while self.should_retry() {
-            let resp = self
-                .client
-                .get(format!("{}/auth", self.url_root))
-                .header("Authorization", &self.auth_token)
-                .header("accept", "text/plain")
-                .send()
-                .await?;
-
-            let status = resp.status();
-            if status.as_u16() == 429 {
-                // dbg!(resp.headers());
-                self.retry_sleep().await;
-                self.increase_retry();
-                continue;
-            }
-            self.decrease_retry();
+    pub async fn is_auth(&self) -> Result<String> {
+        let url = "/auth";
+        let resp = self.get_raw(url, "text/plain").await?;
 
-            if status.is_success() {
-                let data = resp.text().await?;
-                return Ok(data);
-            } else {
-                bail!("Request not successful: {:?}", status);
-            }
+        if resp.status.is_success() {
+            Ok(String::from_utf8_lossy(&resp.body).into_owned())
+        } else {
+            Err(Self::status_error(url, resp.status))
         }
-        bail!("Failed after too many retries")
     }
 
-    pub async fn get_planet(&self, planet_id: &str) -> anyhow::Result<types::Planet> {
-        if let Some(cached) = self.planet_cache.get(planet_id) {
-            if cached.expiry > Utc::now() {
-                return Ok(cached.data.clone());
-            }
+    pub async fn get_planet(&self, planet_id: &str) -> Result<types::Planet> {
+        let url = format!("/planet/{planet_id}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self.planet_cache.get(planet_id) {
+            return Ok(data);
         }
-        let resp: Option<serde_json::Value> = self.request(&format!("/planet/{planet_id}")).await?;
 
-        // planet info is cached for 24 hours
+        let Fetched { data: resp, meta } = self.request::<serde_json::Value>(&url).await?;
         if let Some(planet) = resp {
-            let data = types::Planet::from_json(planet).with_context(|| {
-                format!("Failed to construct Planet object for planet_id={planet_id}")
-            })?;
-            self.planet_cache.insert(
-                planet_id.to_string(),
-                CachedData::new(data.clone(), Duration::from_secs(86400)),
-            );
+            let data = types::Planet::from_json(planet).map_err(Error::decode(&url))?;
+            self.planet_cache
+                .insert(planet_id.to_string(), data.clone(), &meta);
             Ok(data)
         } else {
-            bail!("Planet does not exist")
+            Err(Error::NotFound { url })
         }
     }
 
-    pub async fn get_planets_for_user(&self, user: &str) -> anyhow::Result<Vec<()>> {
-        let _resp: Option<serde_json::Value> =
-            self.request(&format!("/sites/planets/{user}")).await?;
-        todo!();
+    /// Returns the population and planetary projects of a planet
+    pub async fn get_planet_infrastructure(
+        &self,
+        planet_id: &str,
+    ) -> Result<types::PlanetInfrastructure> {
+        let url = format!("/infrastructure/{planet_id}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self.infrastructure_cache.get(planet_id) {
+            return Ok(data);
+        }
+
+        let Fetched { data: resp, meta } = self.request::<Value>(&url).await?;
+        if let Some(data) = resp {
+            let data: types::PlanetInfrastructure =
+                serde_json::from_value(data).map_err(Error::decode(&url))?;
+            self.infrastructure_cache
+                .insert(planet_id.to_string(), data.clone(), &meta);
+            Ok(data)
+        } else {
+            Err(Error::NotFound { url })
+        }
+    }
+
+    /// Returns every planet the user has a base on
+    ///
+    /// Unlike [`get_storage_planets_for_user`](Self::get_storage_planets_for_user), this includes sites without a store.
+    pub async fn get_planets_for_user(&self, user: &str) -> Result<Vec<types::SiteSummary>> {
+        let url = format!("/sites/planets/{user}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self.site_planets_cache.get(user) {
+            return Ok(data);
+        }
+
+        let Fetched { data: resp, meta } = self.request::<Vec<String>>(&url).await?;
+
+        let mut v = Vec::new();
+        for planet_id in resp.unwrap_or_default() {
+            let planet = self.get_planet(&planet_id).await?;
+            v.push(types::SiteSummary::from(&planet));
+        }
+        self.site_planets_cache
+            .insert(user.to_string(), v.clone(), &meta);
+        Ok(v)
+    }
+
+    /// Returns the sites (bases) of the user on every planet, including their buildings
+    pub async fn get_all_sites_for_user(&self, user: &str) -> Result<Vec<types::PlanetSite>> {
+        let url = format!("/sites/{user}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self.sites_cache.get(user) {
+            return Ok(data);
+        }
+
+        let Fetched { data: resp, meta } = self.request::<Vec<serde_json::Value>>(&url).await?;
+
+        let mut v = Vec::new();
+        for obj in resp.unwrap_or_default() {
+            let site: types::PlanetSite =
+                serde_json::from_value(obj).map_err(Error::decode(&url))?;
+            v.push(site);
+        }
+        self.sites_cache.insert(user.to_string(), v.clone(), &meta);
+        Ok(v)
     }
 
     pub async fn get_planetsite_for_user(
         &self,
         user: &str,
         planet_id: &str,
-    ) -> anyhow::Result<types::PlanetSite> {
-        let resp: Option<serde_json::Value> =
-            self.request(&format!("/sites/{user}/{planet_id}")).await?;
+    ) -> Result<types::PlanetSite> {
+        let url = format!("/sites/{user}/{planet_id}");
+        let resp = self.request::<serde_json::Value>(&url).await?.data;
 
         if let Some(v) = resp {
-            Ok(serde_json::from_value(v)?)
+            serde_json::from_value(v).map_err(Error::decode(&url))
         } else {
-            bail!("No planet found")
+            Err(Error::NotFound { url })
         }
     }
 
     pub async fn get_warehouse_info_for_user(
         &self,
         user: &str,
-    ) -> anyhow::Result<Vec<types::WarehouseInfo>> {
-        if let Some(cached) = self.warehouse_cache.get(user) {
-            if cached.expiry > Utc::now() {
-                return Ok(cached.data.clone());
-            }
+    ) -> Result<Vec<types::WarehouseInfo>> {
+        let url = format!("/sites/warehouses/{user}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self.warehouse_cache.get(user) {
+            return Ok(data);
         }
 
-        let resp: Option<serde_json::Value> = self.request(&format!("/sites/warehouses/{user}")).await?;
+        let Fetched { data: resp, meta } = self.request::<serde_json::Value>(&url).await?;
 
         let mut v = Vec::new();
         if let Some(Value::Array(list)) = resp {
             for obj in list.into_iter() {
-                let sto: WarehouseInfo = serde_json::from_value(obj).unwrap();
+                let sto: WarehouseInfo =
+                    serde_json::from_value(obj).map_err(Error::decode(&url))?;
 
                 v.push(sto);
             }
         }
-
-        // warehouse info is cached for 1 hour
-        self.warehouse_cache.insert(
-            user.to_string(),
-            CachedData::new(v.clone(), Duration::from_secs(3600)),
-        );
+        self.warehouse_cache
+            .insert(user.to_string(), v.clone(), &meta);
         Ok(v)
     }
 
-    pub async fn get_all_storage_for_user(
-        &self,
-        user: &str,
-    ) -> anyhow::Result<Vec<types::Storage>> {
-        if let Some(cached) = self.storage_user_cache.get(user) {
-            if cached.expiry > Utc::now() {
-                return Ok(cached.data.clone());
-            }
+    pub async fn get_all_storage_for_user(&self, user: &str) -> Result<Vec<types::Storage>> {
+        let url = format!("/storage/{user}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self.storage_user_cache.get(user) {
+            return Ok(data);
         }
-        let resp: Option<serde_json::Value> = self.request(&format!("/storage/{user}")).await?;
+
+        let Fetched { data: resp, meta } = self.request::<serde_json::Value>(&url).await?;
 
         let mut v = Vec::new();
         if let Some(Value::Array(list)) = resp {
             for obj in list.into_iter() {
-                let sto = types::Storage::from_json(obj).unwrap();
+                let sto = types::Storage::from_json(obj).map_err(Error::decode(&url))?;
 
                 v.push(sto);
             }
         }
-
-        // storage info is cached for 15 minutes
-        self.storage_user_cache.insert(
-            user.to_string(),
-            CachedData::new(v.clone(), Duration::from_secs(900)),
-        );
+        self.storage_user_cache
+            .insert(user.to_string(), v.clone(), &meta);
         Ok(v)
     }
 
+    /// Like [`get_all_storage_for_user`](Self::get_all_storage_for_user), but always fetches current storage
+    pub async fn get_all_storage_for_user_fresh(&self, user: &str) -> Result<Vec<types::Storage>> {
+        self.invalidate(CacheScope::UserStorage(user.to_string()))
+            .await?;
+        self.get_all_storage_for_user(user).await
+    }
+
     /// Store can be a StorageId, a PlanetId, PlanetNaturalId, or PlanetName
     pub async fn get_storage_for_user(
         &self,
         user: &str,
         store: &str,
-    ) -> anyhow::Result<Option<types::Storage>> {
-        if let Some(cached) = self
+    ) -> Result<Option<types::Storage>> {
+        let url = format!("/storage/{user}/{store}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self
             .storage_cache
             .get(&(user.to_string(), store.to_string()))
         {
-            if cached.expiry > Utc::now() {
-                return Ok(cached.data.clone());
-            }
+            return Ok(data);
         }
 
-        let resp: Option<serde_json::Value> =
-            self.request(&format!("/storage/{user}/{store}")).await?;
+        let Fetched { data: resp, meta } = self.request::<serde_json::Value>(&url).await?;
 
         let data = if let Some(sto) = resp {
-            Some(types::Storage::from_json(sto)?)
+            Some(types::Storage::from_json(sto).map_err(Error::decode(&url))?)
         } else {
             None
         };
-
-        // storage info is cached for 15 minutes
-        self.storage_cache.insert(
-            (user.to_string(), store.to_string()),
-            CachedData::new(data.clone(), Duration::from_secs(900)),
-        );
+        self.storage_cache
+            .insert((user.to_string(), store.to_string()), data.clone(), &meta);
 
         Ok(data)
     }
 
-    /// Returns a list of planet IDs (AB-123x) where the given user has storage
-    pub async fn get_storage_planets_for_user(
+    /// Like [`get_storage_for_user`](Self::get_storage_for_user), but always fetches current storage
+    pub async fn get_storage_for_user_fresh(
         &self,
         user: &str,
-    ) -> anyhow::Result<Vec<types::Planet>> {
-        if let Some(cached) = self.planet_info_cache.get(user) {
-            if cached.expiry > Utc::now() {
-                return Ok(cached.data.clone());
-            }
+        store: &str,
+    ) -> Result<Option<types::Storage>> {
+        self.invalidate(CacheScope::Store {
+            user: user.to_string(),
+            store: store.to_string(),
+        })
+        .await?;
+        self.get_storage_for_user(user, store).await
+    }
+
+    /// Returns a list of planet IDs (AB-123x) where the given user has storage
+    pub async fn get_storage_planets_for_user(&self, user: &str) -> Result<Vec<types::Planet>> {
+        let url = format!("/storage/planets/{user}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self.planet_info_cache.get(user) {
+            return Ok(data);
         }
 
-        let resp: Option<Vec<String>> = self.request(&format!("/storage/planets/{user}")).await?;
+        let Fetched { data: resp, meta } = self.request::<Vec<String>>(&url).await?;
 
         let mut v = Vec::new();
         if let Some(planets) = resp {
@@ -510,12 +681,8 @@ impl FIOClient {
                 v.push(planet)
             }
         }
-
-        // storage info is cached for 24 hours
-        self.planet_info_cache.insert(
-            user.to_string(),
-            CachedData::new(v.clone(), Duration::from_secs(86400)),
-        );
+        self.planet_info_cache
+            .insert(user.to_string(), v.clone(), &meta);
 
         Ok(v)
     }
@@ -524,221 +691,578 @@ impl FIOClient {
         &self,
         user: &str,
         planet: &str,
-    ) -> anyhow::Result<types::PlanetWorkforce> {
-        if let Some(cached) = self
+    ) -> Result<types::PlanetWorkforce> {
+        let url = format!("/workforce/{user}/{planet}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self
             .workforce_cache
             .get(&(user.to_string(), planet.to_string()))
         {
-            if cached.expiry > Utc::now() {
-                return Ok(cached.data.clone());
-            }
+            return Ok(data);
         }
 
-        let resp: Option<serde_json::Value> =
-            self.request(&format!("/workforce/{user}/{planet}")).await?;
-
-        let data = types::PlanetWorkforce::from_json(resp.context("No workforce on planet")?)?;
+        let Fetched { data: resp, meta } = self.request::<serde_json::Value>(&url).await?;
 
-        // workforce info is cached for 2 hours
-        self.workforce_cache.insert(
-            (user.to_string(), planet.to_string()),
-            CachedData::new(data.clone(), Duration::from_secs(7200)),
-        );
+        let Some(resp) = resp else {
+            return Err(Error::NotFound { url });
+        };
+        let data = types::PlanetWorkforce::from_json(resp).map_err(Error::decode(&url))?;
+        self.workforce_cache
+            .insert((user.to_string(), planet.to_string()), data.clone(), &meta);
 
         Ok(data)
     }
 
-    pub async fn get_planet_localmarket(&self, planet: &str) -> anyhow::Result<types::LocalMarket> {
-        if let Some(cached) = self.localmarket_cache.get(planet) {
-            if cached.expiry > Utc::now() {
-                return Ok(cached.data.clone());
-            }
+    pub async fn get_planet_localmarket(&self, planet: &str) -> Result<types::LocalMarket> {
+        let url = format!("/localmarket/planet/{planet}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self.localmarket_cache.get(planet) {
+            return Ok(data);
         }
 
-        let resp: Option<serde_json::Value> = self
-            .request(&format!("/localmarket/planet/{planet}"))
-            .await?;
+        let Fetched { data: resp, meta } = self.request::<serde_json::Value>(&url).await?;
 
         let data = if let Some(data) = resp {
-            types::LocalMarket::from_json(data)?
+            types::LocalMarket::from_json(data).map_err(Error::decode(&url))?
         } else {
             Default::default()
         };
-
-        // localmarket info is cached for 10 minutes
-        self.localmarket_cache.insert(
-            planet.to_string(),
-            CachedData::new(data.clone(), Duration::from_secs(600)),
-        );
+        self.localmarket_cache
+            .insert(planet.to_string(), data.clone(), &meta);
         Ok(data)
     }
 
-    pub async fn get_exchange_info(&self, ticker: &str) -> anyhow::Result<types::Ticker> {
-        if let Some(cached) = self.exchange_cache.get(ticker) {
-            if cached.expiry > Utc::now() {
-                return Ok(cached.data.clone());
-            }
+    pub async fn get_exchange_info(&self, ticker: &str) -> Result<types::Ticker> {
+        // it's more efficient to get the full exchange info (and cache it), than it is to request info on each ticker we need
+        let snapshot = self.get_all_tickers().await?;
+        snapshot
+            .tickers
+            .get(ticker)
+            .cloned()
+            .ok_or_else(|| Error::NotFound {
+                url: format!("/exchange/{ticker}"),
+            })
+    }
+
+    /// Returns every ticker of every exchange
+    ///
+    /// Tickers that FIO sent in an unexpected format are listed in [`ExchangeSnapshot::rejected`](types::ExchangeSnapshot::rejected)
+    /// instead of failing the whole snapshot.
+    pub async fn get_all_tickers(&self) -> Result<Arc<types::ExchangeSnapshot>> {
+        let url = "/exchange/full";
+        if let Some(data) = self.exchange_cache.get(url) {
+            return Ok(data);
+        }
+        let _flight = self.flights.lock(url).await;
+        if let Some(data) = self.exchange_cache.get(url) {
+            return Ok(data);
         }
 
-        // it's more efficient to get the full exchange info (and cache it), than it is to request info on each ticker we need
-        // sometimes this fails because the JSON is truncated.  so if it fails, wait a new milliseconds and try again
-        let resp: Option<Vec<serde_json::Value>> =
-            if let Ok(x) = self.request("/exchange/full").await {
-                x
-            } else {
-                tokio::time::sleep(Duration::from_millis(25)).await;
-                self.request("/exchange/full").await?
-            };
+        let Fetched { data: resp, meta } = self.request::<Vec<Box<RawValue>>>(url).await?;
 
-        if let Some(data) = resp {
-            // ticker data is cached for 15 minutes
-            for ticker_data in data {
-                let x = serde_json::to_string_pretty(&ticker_data).unwrap();
-                let individual_ticker = types::Ticker::from_json(ticker_data).with_context(|| x)?;
-                self.exchange_cache.insert(
-                    individual_ticker.name.clone(),
-                    CachedData::new(individual_ticker, Duration::from_secs(900)),
-                );
-            }
+        let entries = resp.ok_or_else(|| Error::NotFound {
+            url: url.to_string(),
+        })?;
+        let snapshot = types::ExchangeSnapshot::from_raw(&entries, meta.fetched_at);
+        if !snapshot.rejected.is_empty() {
+            warn!(
+                rejected = snapshot.rejected.len(),
+                "Some exchange tickers could not be parsed"
+            );
+        }
+
+        let snapshot = Arc::new(snapshot);
+        self.exchange_cache
+            .insert(url.to_string(), snapshot.clone(), &meta);
+        Ok(snapshot)
+    }
 
-            // now get our data out of the cache
-            Ok(self
-                .exchange_cache
-                .get(ticker)
-                .map(|t| t.data.clone())
-                .context("No exchange info found for this ticker")?)
+    /// Like [`get_exchange_info`](Self::get_exchange_info), but always fetches current exchange data
+    pub async fn get_exchange_info_fresh(&self, ticker: &str) -> Result<types::Ticker> {
+        self.invalidate(CacheScope::Ticker(ticker.to_string()))
+            .await?;
+        self.get_exchange_info(ticker).await
+    }
+
+    /// Returns the price history of a ticker, like `"RAT.CI1"`
+    pub async fn get_price_history(&self, ticker: &str) -> Result<Arc<types::PriceHistory>> {
+        let url = format!("/exchange/cxpc/{ticker}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self.price_history_cache.get(ticker) {
+            return Ok(data);
+        }
+
+        let Fetched { data: resp, meta } = self.request::<Value>(&url).await?;
+        let candles = if let Some(data) = resp {
+            serde_json::from_value(data).map_err(Error::decode(&url))?
         } else {
-            bail!("No exchange info found for this ticker")
+            // never traded
+            Vec::new()
+        };
+
+        let data = Arc::new(types::PriceHistory::new(ticker, candles));
+        self.price_history_cache
+            .insert(ticker.to_string(), data.clone(), &meta);
+        Ok(data)
+    }
+
+    /// The price per unit of a ticker according to the configured [`PriceBasis`](types::PriceBasis)
+    ///
+    /// Returns `None` for [`PriceBasis::Spot`](types::PriceBasis::Spot), or if there is no usable
+    /// average, so callers can fall back to their usual spot price.
+    async fn average_price(&self, ticker: &str) -> Option<f32> {
+        let types::PriceBasis::Average { days } = self.price_basis else {
+            return None;
+        };
+        match self.get_price_history(ticker).await {
+            Ok(history) => history.vwap(days, Utc::now()),
+            Err(e) => {
+                warn!(ticker, "Failed to get price history, using spot price: {e}");
+                None
+            }
         }
     }
 
-    pub async fn get_all_materials(&self) -> anyhow::Result<HashMap<String, types::MaterialInfo>> {
-        let resp: Option<Vec<serde_json::Value>> = self.request("/material/allmaterials").await?;
+    /// Returns all materials, by ticker
+    pub async fn get_all_materials(
+        &self,
+    ) -> Result<Arc<types::Catalog<HashMap<String, types::MaterialInfo>>>> {
+        self.get_catalog(
+            &self.materials_cache,
+            "/material/allmaterials",
+            "Ticker",
+            |materials: Vec<types::MaterialInfo>| {
+                materials
+                    .into_iter()
+                    .map(|m| (m.ticker.clone(), m))
+                    .collect()
+            },
+        )
+        .await
+    }
 
-        let mut map = HashMap::new();
-        if let Some(materials) = resp {
-            for material in materials {
-                if let Ok(material) = types::MaterialInfo::from_json(material.clone()) {
-                    map.insert(material.ticker.clone(), material);
-                } else {
-                    println!("Failed to parse material");
-                    println!("{:?}", material);
-                }
-            }
+    /// Returns all buildings, by ticker
+    pub async fn get_all_buildings(
+        &self,
+    ) -> Result<Arc<types::Catalog<HashMap<String, types::BuildingInfo>>>> {
+        self.get_catalog(
+            &self.buildings_cache,
+            "/building/allbuildings",
+            "Ticker",
+            |buildings: Vec<types::BuildingInfo>| {
+                buildings
+                    .into_iter()
+                    .map(|b| (b.ticker.clone(), b))
+                    .collect()
+            },
+        )
+        .await
+    }
+
+    pub async fn get_all_recipes(&self) -> Result<Arc<types::Catalog<Vec<types::Recipe>>>> {
+        self.get_catalog(
+            &self.recipes_cache,
+            "/recipes/allrecipes",
+            "StandardRecipeName",
+            |recipes| recipes,
+        )
+        .await
+    }
+
+    /// Returns every planet, including resources and environment
+    pub async fn get_all_planets(&self) -> Result<Arc<types::Catalog<Vec<types::Planet>>>> {
+        self.get_catalog(
+            &self.all_planets_cache,
+            "/planet/allplanets/full",
+            "PlanetNaturalId",
+            |planets| planets,
+        )
+        .await
+    }
+
+    /// Returns all planets that match the query
+    pub async fn search_planets(&self, query: &types::PlanetQuery) -> Result<Vec<types::Planet>> {
+        let all = self.get_all_planets().await?;
+        Ok(all
+            .entries
+            .iter()
+            .filter(|p| query.matches(p))
+            .cloned()
+            .collect())
+    }
+
+    /// Returns the star map, with every system, exchange station and planet
+    ///
+    /// The built map is cached on disk like any other catalog data, so a new client doesn't need
+    /// the network or have to index the systems again.
+    pub async fn get_star_map(&self) -> Result<Arc<starmap::StarMap>> {
+        let url = "/systemstars";
+        let _flight = self.flights.lock(url).await;
+        if let Some(data) = self.star_map_cache.get(url) {
+            return Ok(data);
+        }
+        // not a FIO endpoint, just where the built map is kept on disk
+        let map_url = "/systemstars/starmap";
+        if let Some(Fetched { data, meta }) = self.cache.load::<starmap::StarMap>(map_url).await {
+            let map = Arc::new(data);
+            self.star_map_cache
+                .insert(url.to_string(), map.clone(), &meta);
+            return Ok(map);
         }
 
+        let Fetched { data: resp, meta } = self.request::<Value>(url).await?;
+        let systems = match resp {
+            Some(data) => serde_json::from_value(data).map_err(Error::decode(url))?,
+            None => return Err(Error::NotFound { url: url.into() }),
+        };
+        let stations_url = "/exchange/station";
+        let stations = match self.request::<Value>(stations_url).await?.data {
+            Some(data) => serde_json::from_value(data).map_err(Error::decode(stations_url))?,
+            None => Vec::new(),
+        };
+        let planets = self.get_all_planets().await?;
+
+        let map = Arc::new(starmap::StarMap::new(systems, stations, &planets.entries));
+        if let Err(e) = self.cache.store(&*map, &self.cache.meta_for(map_url)).await {
+            warn!("Failed to write disk cache: {e}");
+        }
+        self.star_map_cache
+            .insert(url.to_string(), map.clone(), &meta);
         Ok(map)
     }
 
-    pub async fn get_all_buildings(&self) -> anyhow::Result<HashMap<String, types::BuildingInfo>> {
-        let resp: Option<Vec<serde_json::Value>> = self.request("/building/allbuildings").await?;
+    /// Estimates the time and fuel it takes to ship cargo, see [`logistics::estimate`]
+    ///
+    /// The fuel is priced at the exchange closest to `origin`.
+    pub async fn estimate_shipping(
+        &self,
+        origin: &str,
+        destination: &str,
+        cargo: logistics::Cargo,
+        ship: &logistics::ShipClass,
+    ) -> Result<logistics::ShippingEstimate> {
+        let map = self.get_star_map().await?;
+        let mut estimate = logistics::estimate(&map, origin, destination, cargo, ship)?;
+
+        let cxid = map
+            .nearest_exchange(origin)
+            .map_or("CI1", |(station, _)| station.comex_code.as_str());
+        let mut fuel_cost = 0.0;
+        for (ticker, amount) in [("SF", estimate.stl_fuel), ("FF", estimate.ftl_fuel)] {
+            if amount <= 0.0 {
+                continue;
+            }
+            let cx_info = self.get_exchange_info(&format!("{ticker}.{cxid}")).await?;
+            fuel_cost += cx_info
+                .buy_cost(amount.ceil() as u32)
+                .ok_or_else(|| Error::MissingData(format!("No price for {}", cx_info.name)))?;
+        }
+        estimate.fuel_cost = Some(fuel_cost);
+        Ok(estimate)
+    }
 
-        let mut map = HashMap::new();
-        if let Some(buildings) = resp {
-            for building in buildings {
-                if let Ok(building) = types::BuildingInfo::from_json(building.clone()) {
-                    map.insert(building.ticker.clone(), building);
-                } else {
-                    println!("Failed to parse building");
-                    println!("{:?}", building);
+    /// Scans all exchanges for arbitrage opportunities, see [`arbitrage::scan`]
+    ///
+    /// With a `ship`, the fuel cost of flying the cargo from the buying to the selling exchange
+    /// is estimated too, and [`ScanOptions::min_net_profit`](arbitrage::ScanOptions::min_net_profit)
+    /// is applied again after shipping.  Opportunities between exchanges that can't be routed, or
+    /// with an unknown cargo size, keep no shipping cost.
+    pub async fn scan_arbitrage(
+        &self,
+        options: &arbitrage::ScanOptions,
+        ship: Option<&logistics::ShipClass>,
+    ) -> Result<Vec<arbitrage::Opportunity>> {
+        let snapshot = self.get_all_tickers().await?;
+        let mut opportunities = arbitrage::scan(&snapshot, options);
+        let Some(ship) = ship else {
+            return Ok(opportunities);
+        };
+
+        let map = self.get_star_map().await?;
+        let station = |code: &str| {
+            map.stations
+                .iter()
+                .find(|s| s.comex_code == code)
+                .map(|s| s.natural_id.clone())
+        };
+        for opportunity in &mut opportunities {
+            let (Some(origin), Some(destination), Some(cargo)) = (
+                station(&opportunity.buy_exchange),
+                station(&opportunity.sell_exchange),
+                opportunity.cargo,
+            ) else {
+                continue;
+            };
+            match self
+                .estimate_shipping(&origin, &destination, cargo, ship)
+                .await
+            {
+                // fuel is bought at the exchange we're leaving from
+                Ok(estimate) => {
+                    opportunity.shipping_cost = estimate
+                        .fuel_cost
+                        .and_then(|cost| options.rates.convert(cost, &opportunity.buy_currency))
                 }
+                Err(e) => debug!(%origin, %destination, "No shipping estimate: {e}"),
             }
         }
-
-        Ok(map)
+        opportunities.retain(|o| o.net_profit() >= options.min_net_profit);
+        arbitrage::rank(&mut opportunities);
+        Ok(opportunities)
     }
 
-    pub async fn get_all_recipes(&self) -> anyhow::Result<Vec<types::Recipe>> {
-        let resp: Option<Vec<serde_json::Value>> = self.request("/recipes/allrecipes").await?;
+    /// Fetches and caches a catalog endpoint
+    ///
+    /// Entries that don't parse are returned in [`Catalog::rejected`](types::Catalog::rejected),
+    /// identified by their `id_field`.
+    async fn get_catalog<T: DeserializeOwned, C>(
+        &self,
+        cache: &MemoryCache<String, Arc<types::Catalog<C>>>,
+        url: &str,
+        id_field: &str,
+        collect: impl FnOnce(Vec<T>) -> C,
+    ) -> Result<Arc<types::Catalog<C>>> {
+        let _flight = self.flights.lock(url).await;
+        if let Some(data) = cache.get(url) {
+            return Ok(data);
+        }
+
+        let Fetched { data: resp, meta } = self.request::<Vec<Box<RawValue>>>(url).await?;
+        let entries = resp.ok_or_else(|| Error::NotFound {
+            url: url.to_string(),
+        })?;
+
+        let (parsed, rejected) =
+            types::parse_entries(&entries, |v| v[id_field].as_str().map(str::to_string));
+        if !rejected.is_empty() {
+            warn!(
+                url,
+                rejected = rejected.len(),
+                "Some catalog entries could not be parsed"
+            );
+        }
 
-        resp.unwrap()
-            .into_iter()
-            .map(types::Recipe::from_json)
-            .collect()
+        let catalog = Arc::new(types::Catalog {
+            entries: collect(parsed),
+            rejected,
+            fetched_at: meta.fetched_at,
+        });
+        cache.insert(url.to_string(), catalog.clone(), &meta);
+        Ok(catalog)
     }
 
     pub async fn get_planet_production(
         &self,
         username: &str,
         planet: &str,
-    ) -> anyhow::Result<Vec<types::ProductionLine>> {
-        if let Some(cached) = self
+    ) -> Result<Vec<types::ProductionLine>> {
+        let url = format!("/production/{username}/{planet}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self
             .planet_production_cache
             .get(&(username.to_string(), planet.to_string()))
         {
-            if cached.expiry > Utc::now() {
-                return Ok(cached.data.clone());
-            }
+            return Ok(data);
         }
 
-        let resp: Option<Vec<serde_json::Value>> = self
-            .request(&format!("/production/{username}/{planet}"))
-            .await?;
+        let Fetched { data: resp, meta } = self.request::<Vec<serde_json::Value>>(&url).await?;
 
         let mut v = Vec::new();
         if let Some(orders) = resp {
             for order in orders {
-                let order = types::ProductionLine::from_json(order)?;
+                let order = types::ProductionLine::from_json(order).map_err(Error::decode(&url))?;
                 v.push(order);
             }
         }
-
-        // production info is cached for 15 minutes
         self.planet_production_cache.insert(
             (username.to_string(), planet.to_string()),
-            CachedData::new(v.clone(), Duration::from_secs(900)),
+            v.clone(),
+            &meta,
         );
 
         Ok(v)
     }
 
-    pub async fn get_cxos_for_user(
+    /// Like [`get_planet_production`](Self::get_planet_production), but always fetches current production lines
+    pub async fn get_planet_production_fresh(
         &self,
         username: &str,
-    ) -> anyhow::Result<Vec<types::OwnMarketOrder>> {
-        if let Some(cached) = self.own_orders_cache.get(username) {
-            if cached.expiry > Utc::now() {
-                return Ok(cached.data.clone());
-            }
+        planet: &str,
+    ) -> Result<Vec<types::ProductionLine>> {
+        self.invalidate(CacheScope::PlanetProduction {
+            user: username.to_string(),
+            planet: planet.to_string(),
+        })
+        .await?;
+        self.get_planet_production(username, planet).await
+    }
+
+    pub async fn get_cxos_for_user(&self, username: &str) -> Result<Vec<types::OwnMarketOrder>> {
+        let url = format!("/cxos/{username}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self.own_orders_cache.get(username) {
+            return Ok(data);
         }
 
-        let resp: Option<Vec<serde_json::Value>> =
-            self.request(&format!("/cxos/{username}")).await?;
+        let Fetched { data: resp, meta } = self.request::<Vec<serde_json::Value>>(&url).await?;
 
         let mut v = Vec::new();
         if let Some(orders) = resp {
             for order in orders {
-                let order = serde_json::from_value(order)?;
+                let order = serde_json::from_value(order).map_err(Error::decode(&url))?;
                 v.push(order);
             }
         }
+        self.own_orders_cache
+            .insert(username.to_string(), v.clone(), &meta);
 
-        // cache for 15 minutes
-        self.own_orders_cache.insert(
-            username.to_string(),
-            CachedData::new(v.clone(), Duration::from_secs(900)),
-        );
+        Ok(v)
+    }
+
+    /// Returns the company profile of a user
+    pub async fn get_user_info(&self, user: &str) -> Result<types::CompanyProfile> {
+        self.get_profile(format!("/user/{user}")).await
+    }
+
+    /// Returns the company profile for a company code, like "EM32"
+    pub async fn get_company(&self, code: &str) -> Result<types::CompanyProfile> {
+        self.get_profile(format!("/company/code/{code}")).await
+    }
+
+    /// Returns the company profile of the logged-in user
+    ///
+    /// Useful to tell our own ads and orders apart from everybody else's.
+    pub async fn get_own_company(&self) -> Result<types::CompanyProfile> {
+        let user = self.is_auth().await?;
+        self.get_user_info(&user).await
+    }
+
+    async fn get_profile(&self, url: String) -> Result<types::CompanyProfile> {
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self.profile_cache.get(&url) {
+            return Ok(data);
+        }
+
+        let Fetched { data: resp, meta } = self.request::<Value>(&url).await?;
+        let data: types::CompanyProfile = if let Some(data) = resp {
+            serde_json::from_value(data).map_err(Error::decode(&url))?
+        } else {
+            return Err(Error::NotFound { url });
+        };
+        self.profile_cache.insert(url, data.clone(), &meta);
+        Ok(data)
+    }
 
+    /// Returns all ships of the user
+    pub async fn get_ships_for_user(&self, user: &str) -> Result<Vec<types::Ship>> {
+        let url = format!("/ship/ships/{user}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self.ships_cache.get(user) {
+            return Ok(data);
+        }
+
+        let Fetched { data: resp, meta } = self.request::<Value>(&url).await?;
+        let v: Vec<types::Ship> = if let Some(data) = resp {
+            serde_json::from_value(data).map_err(Error::decode(&url))?
+        } else {
+            Vec::new()
+        };
+        self.ships_cache.insert(user.to_string(), v.clone(), &meta);
         Ok(v)
     }
 
+    /// Returns the flights the user's ships are currently on
+    pub async fn get_flights_for_user(&self, user: &str) -> Result<Vec<types::Flight>> {
+        let url = format!("/ship/flights/{user}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self.flights_cache.get(user) {
+            return Ok(data);
+        }
+
+        let Fetched { data: resp, meta } = self.request::<Value>(&url).await?;
+        let v: Vec<types::Flight> = if let Some(data) = resp {
+            serde_json::from_value(data).map_err(Error::decode(&url))?
+        } else {
+            Vec::new()
+        };
+        self.flights_cache
+            .insert(user.to_string(), v.clone(), &meta);
+        Ok(v)
+    }
+
+    /// Returns all ships of the user, along with what's in their stores and where they're flying
+    pub async fn get_ship_status_for_user(&self, user: &str) -> Result<Vec<types::ShipStatus>> {
+        let ships = self.get_ships_for_user(user).await?;
+        let storages = self.get_all_storage_for_user(user).await?;
+        let flights = self.get_flights_for_user(user).await?;
+        Ok(types::ShipStatus::link(ships, &storages, &flights))
+    }
+
+    /// Returns all contracts of the user, including finished ones
+    pub async fn get_contracts_for_user(&self, user: &str) -> Result<Vec<types::Contract>> {
+        let url = format!("/contract/allcontracts/{user}");
+        let _flight = self.flights.lock(&url).await;
+        if let Some(data) = self.contracts_cache.get(user) {
+            return Ok(data);
+        }
+
+        let Fetched { data: resp, meta } = self.request::<Value>(&url).await?;
+        let v: Vec<types::Contract> = if let Some(data) = resp {
+            serde_json::from_value(data).map_err(Error::decode(&url))?
+        } else {
+            Vec::new()
+        };
+        self.contracts_cache
+            .insert(user.to_string(), v.clone(), &meta);
+        Ok(v)
+    }
+
+    /// The cost of buying the base materials of a building at an exchange, like `"CI1"`
+    ///
+    /// Doesn't include the extra materials a planet's environment calls for, see
+    /// [`calc_building_cost_on_planet`](Self::calc_building_cost_on_planet) for those.
     pub async fn calc_building_cost(
         &self,
         building_ticker: &str,
         planet_cxid: &str,
-    ) -> anyhow::Result<f32> {
-        let building = get_building_db().get(building_ticker).unwrap();
+    ) -> Result<f32> {
+        let building = lookup_building(building_ticker)?;
+        self.calc_materials_cost(building.building_cost, planet_cxid)
+            .await
+    }
+
+    /// The cost of buying everything needed to construct a building on a planet, at the exchange `cx`
+    ///
+    /// This includes the extra materials that the planet's environment calls for, see
+    /// [`Planet::building_materials`](types::Planet::building_materials).
+    pub async fn calc_building_cost_on_planet(
+        &self,
+        building_ticker: &str,
+        planet_id: &str,
+        cx: &str,
+    ) -> Result<f32> {
+        let building = lookup_building(building_ticker)?;
+        let planet = self.get_planet(planet_id).await?;
+        self.calc_materials_cost(&planet.building_materials(building), cx)
+            .await
+    }
+
+    async fn calc_materials_cost(
+        &self,
+        materials: &[(&str, u32)],
+        planet_cxid: &str,
+    ) -> Result<f32> {
         let mut total_cost = 0.0;
-        for (ticker, amount) in building.building_cost.iter() {
-            let cx_info = self
-                .get_exchange_info(&format!("{}.{planet_cxid}", ticker))
-                .await?;
-            let total = cx_info
-                .instant_buy(*amount)
-                .map(|o| o.total_value)
-                .or_else(|| cx_info.get_any_price())
-                .unwrap();
-            total_cost += total;
+        for (ticker, amount) in materials {
+            let full_ticker = format!("{}.{planet_cxid}", ticker);
+            if let Some(price) = self.average_price(&full_ticker).await {
+                total_cost += price * *amount as f32;
+                continue;
+            }
+            let cx_info = self.get_exchange_info(&full_ticker).await?;
+            total_cost += cx_info
+                .buy_cost(*amount)
+                .ok_or_else(|| Error::MissingData(format!("No price for {}", cx_info.name)))?;
         }
 
         Ok(total_cost)
@@ -753,7 +1277,7 @@ impl FIOClient {
         building_ticker: &str,
         material_ticker: &str,
         cogm: Option<&HashMap<String, COGMSource>>,
-    ) -> anyhow::Result<Option<f32>> {
+    ) -> Result<Option<f32>> {
         trace!(username);
 
         let planet_obj = self.get_planet(planet).await?;
@@ -779,11 +1303,10 @@ impl FIOClient {
                 .map(|s| s.items)
                 .unwrap_or_default();
 
-            let building: &building_db::StaticBuildingInfo =
-                get_building_db().get(prod.building_type.as_str()).unwrap();
+            let building = lookup_building(&prod.building_type)?;
             // dbg!(building);
             let building_cost = self
-                .calc_building_cost(building.ticker, planet_cxid)
+                .calc_building_cost_on_planet(building.ticker, planet, planet_cxid)
                 .await?;
             // assume we repair our buildings after 90 days
             let repair_cost = building_cost - (building_cost * 0.5).floor();
@@ -791,8 +1314,7 @@ impl FIOClient {
 
             let wf = self
                 .get_planet_workforce_for_user(username, &prod.planet_id)
-                .await
-                .unwrap();
+                .await?;
 
             // find an order for this material
             let order = prod
@@ -806,7 +1328,9 @@ impl FIOClient {
                             .any(|o| o.material_ticker == material_ticker)
                     // && order.started.is_none()
                 })
-                .context("Failed to find order")?;
+                .ok_or_else(|| {
+                    Error::MissingData(format!("No production order for {material_ticker}"))
+                })?;
 
             total_daily_costs += daily_repair_cost;
             // production scale -- multiple by this to compute how much stuff is produced per day
@@ -816,21 +1340,25 @@ impl FIOClient {
             let day_scale = 86400.0 / order.duration.unwrap().as_secs() as f32;
             for input in &order.inputs {
                 let daily_buy_amt = input.material_amount as f32 * day_scale;
-                let cx_info = self
-                    .get_exchange_info(&format!("{}.{planet_cxid}", input.material_ticker))
-                    .await
-                    .unwrap();
-
-                let market_costs =
-                    if let Some(total) = cx_info.instant_buy(daily_buy_amt.ceil() as u32) {
-                        total.total_value
-                    } else if let Some(x) = cx_info.price {
-                        x * daily_buy_amt
-                    } else if let Some(x) = cx_info.get_any_price() {
-                        x * daily_buy_amt
-                    } else {
-                        0.0
-                    };
+                let full_ticker = format!("{}.{planet_cxid}", input.material_ticker);
+                let cx_info = self.get_exchange_info(&full_ticker).await?;
+
+                let book = cx_info.order_book();
+                let fill = book.fill_buy(daily_buy_amt.ceil() as u32, true);
+                let market_costs = if let Some(x) = self.average_price(&full_ticker).await {
+                    x * daily_buy_amt
+                } else if fill.is_complete() {
+                    fill.total_value
+                } else if let Some(x) = fill
+                    .worst_price
+                    .or(cx_info.price)
+                    .or_else(|| book.mid_price())
+                    .or_else(|| cx_info.get_any_price())
+                {
+                    fill.total_with_fallback(x)
+                } else {
+                    0.0
+                };
 
                 // println!(
                 //     "Market costs for {} units of {}: {}",
@@ -850,7 +1378,7 @@ impl FIOClient {
                         // );
                         total_daily_costs += x
                     }
-                    x => total_daily_costs += market_costs,
+                    _ => total_daily_costs += market_costs,
                 }
 
                 // println!(
@@ -872,7 +1400,7 @@ impl FIOClient {
                 num_workers: u32,
                 planet_cxid: &str,
                 details: &WorkforceDetails,
-            ) -> f32 {
+            ) -> Result<f32> {
                 let mut total = 0.0;
                 for need in &details.needs {
                     // only include this needed consumable if we have it in our inventory, or if it's essential
@@ -881,12 +1409,11 @@ impl FIOClient {
                         let daily = need.units_per_one_hundred * (num_workers as f32 / 100.0);
                         let cx_info = client
                             .get_exchange_info(&format!("{}.{planet_cxid}", need.ticker))
-                            .await
-                            .unwrap();
-                        total += cx_info.ask.or_else(|| cx_info.get_any_price()).unwrap() * daily;
+                            .await?;
+                        total += need_price(&cx_info)? * daily;
                     }
                 }
-                total
+                Ok(total)
             }
 
             let mut workforce_costs = 0.0;
@@ -897,9 +1424,9 @@ impl FIOClient {
                     &inv,
                     building.pioneers,
                     planet_cxid,
-                    wf.details.get(types::PlanetWorkforce::PIONEER).unwrap(),
+                    workforce_details(&wf, types::PlanetWorkforce::PIONEER)?,
                 )
-                .await;
+                .await?;
             }
             if building.settlers > 0 {
                 workforce_costs += add_needs(
@@ -907,9 +1434,9 @@ impl FIOClient {
                     &inv,
                     building.settlers,
                     planet_cxid,
-                    wf.details.get(types::PlanetWorkforce::SETTLER).unwrap(),
+                    workforce_details(&wf, types::PlanetWorkforce::SETTLER)?,
                 )
-                .await;
+                .await?;
             }
             if building.technicians > 0 {
                 workforce_costs += add_needs(
@@ -917,9 +1444,9 @@ impl FIOClient {
                     &inv,
                     building.technicians,
                     planet_cxid,
-                    wf.details.get(types::PlanetWorkforce::TECHNICIAN).unwrap(),
+                    workforce_details(&wf, types::PlanetWorkforce::TECHNICIAN)?,
                 )
-                .await;
+                .await?;
             }
             if building.engineers > 0 {
                 workforce_costs += add_needs(
@@ -927,9 +1454,9 @@ impl FIOClient {
                     &inv,
                     building.engineers,
                     planet_cxid,
-                    wf.details.get(types::PlanetWorkforce::ENGINEER).unwrap(),
+                    workforce_details(&wf, types::PlanetWorkforce::ENGINEER)?,
                 )
-                .await;
+                .await?;
             }
             if building.scientists > 0 {
                 workforce_costs += add_needs(
@@ -937,15 +1464,15 @@ impl FIOClient {
                     &inv,
                     building.scientists,
                     planet_cxid,
-                    wf.details.get(types::PlanetWorkforce::SCIENTIST).unwrap(),
+                    workforce_details(&wf, types::PlanetWorkforce::SCIENTIST)?,
                 )
-                .await;
+                .await?;
             }
 
             let new_workforce_cost_calc = self
                 .calc_workforce_costs(username, planet, building_ticker, true, true)
                 .await?;
-            if new_workforce_cost_calc.abs_sub(workforce_costs) > 1.0 {
+            if (new_workforce_cost_calc - workforce_costs).abs() > 1.0 {
                 warn!(
                     old = workforce_costs,
                     new = new_workforce_cost_calc,
@@ -968,10 +1495,8 @@ impl FIOClient {
         building_ticker: &str,
         lux1: bool,
         lux2: bool,
-    ) -> anyhow::Result<f32> {
-        let building = get_building_db()
-            .get(building_ticker)
-            .context("No such building")?;
+    ) -> Result<f32> {
+        let building = lookup_building(building_ticker)?;
 
         let planet = self.get_planet(planet_id).await?;
         let planet_cxid = planet.get_cx_mid().unwrap_or("CI1");
@@ -980,8 +1505,7 @@ impl FIOClient {
 
         let wf = self
             .get_planet_workforce_for_user(username, planet_id)
-            .await
-            .unwrap();
+            .await?;
 
         async fn add_needs(
             client: &FIOClient,
@@ -990,7 +1514,7 @@ impl FIOClient {
             planet_cxid: &str,
             lux1: bool,
             lux2: bool,
-        ) -> f32 {
+        ) -> Result<f32> {
             let mut total = 0.0;
             for need in &details.needs {
                 // TODO handle lux1 and lux2
@@ -1003,75 +1527,378 @@ impl FIOClient {
                     let daily = need.units_per_one_hundred * (num_workers as f32 / 100.0);
                     let cx_info = client
                         .get_exchange_info(&format!("{}.{planet_cxid}", need.ticker))
-                        .await
-                        .unwrap();
-                    total += cx_info.ask.or_else(|| cx_info.get_any_price()).unwrap() * daily;
+                        .await?;
+                    total += need_price(&cx_info)? * daily;
                 }
             }
-            total
+            Ok(total)
         }
 
         if building.pioneers > 0 {
             total_daily_costs += add_needs(
                 self,
                 building.pioneers,
-                wf.details.get(types::PlanetWorkforce::PIONEER).unwrap(),
+                workforce_details(&wf, types::PlanetWorkforce::PIONEER)?,
                 planet_cxid,
                 lux1,
                 lux2,
             )
-            .await;
+            .await?;
         }
         if building.settlers > 0 {
             total_daily_costs += add_needs(
                 self,
                 building.settlers,
-                wf.details.get(types::PlanetWorkforce::SETTLER).unwrap(),
+                workforce_details(&wf, types::PlanetWorkforce::SETTLER)?,
                 planet_cxid,
                 lux1,
                 lux2,
             )
-            .await;
+            .await?;
         }
         if building.technicians > 0 {
             total_daily_costs += add_needs(
                 self,
                 building.technicians,
-                wf.details.get(types::PlanetWorkforce::TECHNICIAN).unwrap(),
+                workforce_details(&wf, types::PlanetWorkforce::TECHNICIAN)?,
                 planet_cxid,
                 lux1,
                 lux2,
             )
-            .await;
+            .await?;
         }
         if building.engineers > 0 {
             total_daily_costs += add_needs(
                 self,
                 building.engineers,
-                wf.details.get(types::PlanetWorkforce::ENGINEER).unwrap(),
+                workforce_details(&wf, types::PlanetWorkforce::ENGINEER)?,
                 planet_cxid,
                 lux1,
                 lux2,
             )
-            .await;
+            .await?;
         }
 
         if building.scientists > 0 {
             total_daily_costs += add_needs(
                 self,
                 building.scientists,
-                wf.details.get(types::PlanetWorkforce::SCIENTIST).unwrap(),
+                workforce_details(&wf, types::PlanetWorkforce::SCIENTIST)?,
                 planet_cxid,
                 lux1,
                 lux2,
             )
-            .await;
+            .await?;
         }
 
         Ok(total_daily_costs)
     }
 }
 
+fn lookup_building(ticker: &str) -> Result<&'static building_db::StaticBuildingInfo> {
+    get_building_db()
+        .get(ticker)
+        .ok_or_else(|| Error::MissingData(format!("Unknown building {ticker}")))
+}
+
+fn workforce_details<'a>(
+    wf: &'a types::PlanetWorkforce,
+    name: &str,
+) -> Result<&'a WorkforceDetails> {
+    wf.details
+        .get(name)
+        .ok_or_else(|| Error::MissingData(format!("No {name} workforce on {}", wf.planet_id)))
+}
+
+/// The price we expect to pay for a workforce consumable
+fn need_price(cx_info: &types::Ticker) -> Result<f32> {
+    cx_info
+        .ask
+        .or_else(|| cx_info.get_any_price())
+        .ok_or_else(|| Error::MissingData(format!("No price for {}", cx_info.name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        logistics::{Cargo, ShipClass},
+        testing::{mock_client, planet_json, ticker_json, Fault, MockServer, MOCK_API_KEY},
+        types::PriceBasis,
+    };
+
+    #[tokio::test]
+    async fn test_retry_and_cache() {
+        let (server, client) = mock_client().await.unwrap();
+        server.inject(
+            "/planet/UV-351a",
+            Fault::RateLimited {
+                retry_after_secs: Some(0),
+            },
+        );
+        server.inject("/planet/UV-351a", Fault::Status(522));
+
+        let planet = client.get_planet("UV-351a").await.unwrap();
+        assert_eq!(planet.natural_id, "UV-351a");
+        assert_eq!(server.hits("/planet/UV-351a"), 3);
+
+        // second call is served from the cache
+        client.get_planet("UV-351a").await.unwrap();
+        assert_eq!(server.hits("/planet/UV-351a"), 3);
+
+        assert!(matches!(
+            client.get_planet("XX-000a").await,
+            Err(Error::NotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_retries_exhausted() {
+        let server = MockServer::start_with_defaults().await.unwrap();
+        for _ in 0..3 {
+            server.inject("/planet/UV-351a", Fault::Status(429));
+        }
+        let client = server
+            .builder()
+            .retry_policy(RetryPolicy {
+                max_attempts: 3,
+                initial_delay: Duration::from_millis(1),
+                ..Default::default()
+            })
+            .build_with_key(MOCK_API_KEY)
+            .unwrap();
+        assert!(matches!(
+            client.get_planet("UV-351a").await,
+            Err(Error::RetriesExhausted { attempts: 3, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_no_content() {
+        let (_server, client) = mock_client().await.unwrap();
+        let lm = client.get_planet_localmarket("UV-351a").await.unwrap();
+        assert!(lm.buying_ads.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_exchange() {
+        let (server, client) = mock_client().await.unwrap();
+        server.route(
+            "/exchange/full",
+            serde_json::json!([
+                ticker_json("RAT", "CI1", 40.0, 1000),
+                {"MaterialTicker": "DW", "ExchangeCode": "CI1"},
+            ]),
+        );
+        server.inject("/exchange/full", Fault::TruncatedBody);
+        server.inject("/exchange/full", Fault::TruncatedBody);
+
+        let rat = client.get_exchange_info("RAT.CI1").await.unwrap();
+        assert_eq!(rat.ask, Some(40.0));
+        assert_eq!(server.hits("/exchange/full"), 3);
+
+        let snapshot = client.get_all_tickers().await.unwrap();
+        assert_eq!(snapshot.tickers.len(), 1);
+        assert_eq!(snapshot.rejected[0].id.as_deref(), Some("DW.CI1"));
+    }
+
+    #[tokio::test]
+    async fn test_material_catalog() {
+        let (server, client) = mock_client().await.unwrap();
+        let catalog = client.get_all_materials().await.unwrap();
+        assert_eq!(catalog.entries["NS"].name, "nutrientSolution");
+        assert!(catalog.rejected.is_empty());
+
+        client.get_all_materials().await.unwrap();
+        assert_eq!(server.hits("/material/allmaterials"), 1);
+
+        server.route(
+            "/material/allmaterials",
+            serde_json::json!([{"Ticker": "DW", "Weight": "heavy"}]),
+        );
+        client.invalidate(CacheScope::All).await.unwrap();
+        let catalog = client.get_all_materials().await.unwrap();
+        assert_eq!(catalog.rejected.len(), 1);
+        assert_eq!(catalog.rejected[0].id.as_deref(), Some("DW"));
+    }
+
+    #[tokio::test]
+    async fn test_relogin() {
+        let server = MockServer::start().await.unwrap();
+        server.set_credentials("user", "hunter2");
+        let client = server
+            .builder()
+            .keep_credentials(true)
+            .build_with_password("user", "hunter2")
+            .await
+            .unwrap();
+        assert!(client.token_expiry().is_some());
+        assert_eq!(client.is_auth().await.unwrap(), "mockuser");
+
+        server.revoke_tokens();
+        assert_eq!(client.is_auth().await.unwrap(), "mockuser");
+        assert_eq!(server.logins(), 2);
+
+        assert!(matches!(
+            server.builder().build_with_password("user", "wrong").await,
+            Err(Error::Auth)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_building_cost() {
+        let (server, client) = mock_client().await.unwrap();
+        let mut planet = planet_json("UV-351a");
+        planet["Surface"] = true.into();
+        planet["Pressure"] = 0.1.into();
+        server.route("/planet/UV-351a", planet);
+
+        let building = &get_building_db()["FP"];
+        let mut materials = building.building_cost.to_vec();
+        materials.push(("MCG", building.area_cost * 4));
+        materials.push(("SEA", building.area_cost));
+
+        let planet = client.get_planet("UV-351a").await.unwrap();
+        let mut bom = planet.building_materials(building);
+        bom.sort();
+        materials.sort();
+        assert_eq!(bom, materials);
+
+        let cost = client
+            .calc_building_cost_on_planet("FP", "UV-351a", "CI1")
+            .await
+            .unwrap();
+        let expected: u32 = materials.iter().map(|(_, n)| n * 10).sum();
+        assert_eq!(cost, expected as f32);
+
+        // only the base materials
+        let cost = client.calc_building_cost("FP", "CI1").await.unwrap();
+        let expected: u32 = building.building_cost.iter().map(|(_, n)| n * 10).sum();
+        assert_eq!(cost, expected as f32);
+    }
+
+    #[tokio::test]
+    async fn test_average_price_basis() {
+        let server = MockServer::start_with_defaults().await.unwrap();
+        let building = &get_building_db()["FP"];
+        // the first material was last traded long ago, the others yesterday
+        let yesterday = (chrono::Utc::now() - chrono::Duration::days(1)).timestamp_millis();
+        for (i, (ticker, _)) in building.building_cost.iter().enumerate() {
+            let date = if i == 0 {
+                1_700_000_000_000i64
+            } else {
+                yesterday
+            };
+            server.route(
+                &format!("/exchange/cxpc/{ticker}.CI1"),
+                serde_json::json!([{
+                    "Interval": "DAY_ONE",
+                    "DateEpochMs": date,
+                    "Open": 5.0,
+                    "Close": 5.0,
+                    "High": 5.0,
+                    "Low": 5.0,
+                    "Volume": 500.0,
+                    "Traded": 100,
+                }]),
+            );
+        }
+
+        let client = server
+            .builder()
+            .price_basis(PriceBasis::Average { days: 7 })
+            .build_with_key(MOCK_API_KEY)
+            .unwrap();
+        let cost = client.calc_building_cost("FP", "CI1").await.unwrap();
+        // a stale average falls back to the spot price of 10
+        let expected: u32 = building
+            .building_cost
+            .iter()
+            .enumerate()
+            .map(|(i, (_, n))| if i == 0 { n * 10 } else { n * 5 })
+            .sum();
+        assert_eq!(cost, expected as f32);
+    }
+
+    #[tokio::test]
+    async fn test_ship_status() {
+        let (_server, client) = mock_client().await.unwrap();
+        let status = client.get_ship_status_for_user("mockuser").await.unwrap();
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].ship.display_name(), "AVI-0123A");
+        assert_eq!(status[0].cargo.as_ref().unwrap().items["RAT"].quantity, 100);
+        assert!(status[0].stl_fuel.is_none());
+        let flight = status[0].flight.as_ref().unwrap();
+        assert_eq!(flight.destination, "KW-688c");
+        assert_eq!(
+            flight.time_remaining(flight.departure),
+            chrono::Duration::minutes(6)
+        );
+        assert_eq!(flight.time_remaining(Utc::now()), chrono::Duration::zero());
+    }
+
+    #[tokio::test]
+    async fn test_own_company() {
+        let (_server, client) = mock_client().await.unwrap();
+        let company = client.get_own_company().await.unwrap();
+        assert_eq!(company.company_code, "MOCK");
+        assert_eq!(company.balance("CIS"), Some(12345.5));
+        assert_eq!(company.balance("NCC"), None);
+        assert_eq!(company.planets[0].planet_name, "Montem");
+
+        assert!(matches!(
+            client.get_company("NOPE").await,
+            Err(Error::NotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_shipping_thin_fuel_book() {
+        let (server, client) = mock_client().await.unwrap();
+        // only 50 SF for sale, and much cheaper on average than in the book
+        let mut sf = ticker_json("SF", "CI1", 10.0, 50);
+        sf["Price"] = 5.0.into();
+        server.route(
+            "/exchange/full",
+            serde_json::json!([sf, ticker_json("FF", "CI1", 20.0, 100000)]),
+        );
+
+        let estimate = client
+            .estimate_shipping("AAS", "BBS", Cargo::default(), &ShipClass::STANDARD)
+            .await
+            .unwrap();
+        // 80 SF and 5 FF, with the 30 SF the book can't cover priced at its worst price
+        assert_eq!(estimate.fuel_cost, Some(80.0 * 10.0 + 5.0 * 20.0));
+    }
+
+    #[tokio::test]
+    async fn test_star_map_disk_cache() {
+        let server = MockServer::start_with_defaults().await.unwrap();
+        let dir = std::env::temp_dir().join(format!("fiors_starmap_{}", std::process::id()));
+        let client = || {
+            server
+                .builder()
+                .local_cache_dir(&dir)
+                .build_with_key(MOCK_API_KEY)
+                .unwrap()
+        };
+        let map = client().get_star_map().await.unwrap();
+        assert_eq!(map.jump_count("AA-001", "Mock Planet"), Some(1));
+        assert!(dir
+            .join(crate::cache::file_name("/systemstars/starmap"))
+            .exists());
+
+        // a new client loads the built map, indexes included
+        let map = client().get_star_map().await.unwrap();
+        assert_eq!(map.jump_count("AA-001", "Mock Planet"), Some(1));
+        assert_eq!(server.hits("/systemstars"), 1);
+        assert_eq!(server.hits("/planet/allplanets/full"), 1);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
+
 #[cfg(any(test, feature = "live_tests"))]
 mod live_tests {
     use crate::{materials::MaterialWithColor, types::PlanetWorkforce};
@@ -1079,9 +1906,10 @@ mod live_tests {
     use super::*;
 
     fn get_test_client() -> FIOClient {
-        let mut client = FIOClient::new_from_env().unwrap();
-        client.local_cache_dir = Some(".fio_cache".into());
-        client
+        FIOClient::builder()
+            .local_cache_dir(".fio_cache")
+            .build_with_key(std::env::var("FIO_AUTH_TOKEN").unwrap())
+            .unwrap()
     }
 
     #[tokio::test]
@@ -1093,19 +1921,30 @@ mod live_tests {
             .request("/storage/eminence32")
             .await
             .unwrap()
+            .data
             .unwrap();
         serde_json::to_writer_pretty(
             std::fs::File::create("test_data/storage_eminence32.json")?,
             &data,
         )?;
 
-        let data: serde_json::Value = client.request("/exchange/SF.CI1").await.unwrap().unwrap();
+        let data: serde_json::Value = client
+            .request("/exchange/SF.CI1")
+            .await
+            .unwrap()
+            .data
+            .unwrap();
         serde_json::to_writer_pretty(
             std::fs::File::create("test_data/exchange_SF_CI1.json")?,
             &data,
         )?;
 
-        let data: serde_json::Value = client.request("/exchange/COT.CI1").await.unwrap().unwrap();
+        let data: serde_json::Value = client
+            .request("/exchange/COT.CI1")
+            .await
+            .unwrap()
+            .data
+            .unwrap();
         serde_json::to_writer_pretty(
             std::fs::File::create("test_data/exchange_COT_CI1.json")?,
             &data,
@@ -1115,6 +1954,7 @@ mod live_tests {
             .request("/material/allmaterials")
             .await
             .unwrap()
+            .data
             .unwrap();
         serde_json::to_writer_pretty(
             std::fs::File::create("test_data/material_allmaterials.json")?,
@@ -1147,7 +1987,10 @@ mod live_tests {
     #[tokio::test]
     async fn test_planet_for_user() {
         let client = get_test_client();
-        // let pli = client.get_planets_for_user("eminence32").await.unwrap();
+        let planets = client.get_planets_for_user("eminence32").await.unwrap();
+        assert!(planets.iter().any(|p| p.name == "Katoa"));
+        let sites = client.get_all_sites_for_user("eminence32").await.unwrap();
+        assert_eq!(sites.len(), planets.len());
         client
             .get_planetsite_for_user("eminence32", "1e39248e468e9a6bb12938ba97b58bcf")
             .await
@@ -1240,7 +2083,7 @@ mod live_tests {
             let building = get_building_db().get(prod.building_type.as_str()).unwrap();
             dbg!(building);
             let building_cost = client
-                .calc_building_cost(building.ticker, "TODO")
+                .calc_building_cost_on_planet(building.ticker, &prod.planet_id, "CI1")
                 .await
                 .unwrap();
             println!("Building cost: {}", building_cost);
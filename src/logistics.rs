@@ -0,0 +1,219 @@
+//! Rough estimates of what it takes to ship cargo from one place to another
+//!
+//! These use a simplified flight model: every trip takes off from the origin and lands at the
+//! destination (one STL leg each, or a single leg within the same system), and jumps along the
+//! shortest route of the [`StarMap`].  Fuel burn scales with the total mass of ship and cargo.
+//! The numbers are good enough to compare options, not to plan an actual flight.
+
+use std::time::Duration;
+
+use crate::{get_material_db, starmap::StarMap, Error, Result};
+
+/// Capacity and fuel consumption of a kind of ship
+///
+/// All fields are public, so a ship that's not covered by the constants can be described as well.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShipClass {
+    pub name: &'static str,
+    /// Cargo capacity, in tonnes
+    pub max_weight: f32,
+    /// Cargo capacity, in cubic meters
+    pub max_volume: f32,
+    /// Mass of the ship itself, in tonnes
+    pub empty_mass: f32,
+    /// SF burned per STL leg (taking off or landing), per 1000 t of total mass
+    pub stl_fuel_per_leg: f32,
+    /// FF burned per parsec of FTL flight, per 1000 t of total mass
+    pub ftl_fuel_per_parsec: f32,
+    /// How long one STL leg takes
+    pub stl_hours_per_leg: f32,
+    /// How long it takes to cover one parsec by FTL
+    pub ftl_hours_per_parsec: f32,
+}
+
+impl ShipClass {
+    /// The usual starter ship, with 500 t / 500 m³ of cargo space
+    pub const STANDARD: ShipClass = ShipClass {
+        name: "Standard",
+        max_weight: 500.0,
+        max_volume: 500.0,
+        empty_mass: 1000.0,
+        stl_fuel_per_leg: 40.0,
+        ftl_fuel_per_parsec: 0.5,
+        stl_hours_per_leg: 4.0,
+        ftl_hours_per_parsec: 0.5,
+    };
+
+    /// A ship with a high-volume cargo bay, for bulky but light cargo
+    pub const HIGH_VOLUME: ShipClass = ShipClass {
+        name: "High volume",
+        max_weight: 500.0,
+        max_volume: 3000.0,
+        empty_mass: 1300.0,
+        ..Self::STANDARD
+    };
+
+    /// A ship with a heavy-load cargo bay, for dense cargo
+    pub const HEAVY_LOAD: ShipClass = ShipClass {
+        name: "Heavy load",
+        max_weight: 3000.0,
+        max_volume: 1000.0,
+        empty_mass: 1300.0,
+        ..Self::STANDARD
+    };
+}
+
+/// Total weight and volume of some cargo
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Cargo {
+    /// In tonnes
+    pub weight: f32,
+    /// In cubic meters
+    pub volume: f32,
+}
+
+impl Cargo {
+    /// The cargo for some amounts of materials, by ticker
+    pub fn from_materials<'a>(materials: impl IntoIterator<Item = (&'a str, u32)>) -> Result<Self> {
+        let db = get_material_db();
+        let mut cargo = Cargo::default();
+        for (ticker, amount) in materials {
+            let mat = db
+                .get(ticker)
+                .ok_or_else(|| Error::MissingData(format!("Unknown material {ticker}")))?;
+            cargo.weight += mat.weight * amount as f32;
+            cargo.volume += mat.volume * amount as f32;
+        }
+        Ok(cargo)
+    }
+}
+
+/// The estimated cost of shipping some cargo, see [`estimate`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShippingEstimate {
+    /// Systems along the route, including origin and destination
+    pub route: Vec<String>,
+    /// Total FTL distance, in parsecs
+    pub distance: f64,
+    /// How many flights it takes to move all the cargo
+    pub trips: u32,
+    /// How long one trip takes
+    pub flight_time: Duration,
+    /// SF burned, over all trips
+    pub stl_fuel: f32,
+    /// FF burned, over all trips
+    pub ftl_fuel: f32,
+    /// What the fuel costs, if it was priced (see [`FIOClient::estimate_shipping`](crate::FIOClient::estimate_shipping))
+    pub fuel_cost: Option<f32>,
+}
+
+impl ShippingEstimate {
+    /// Number of jumps along the route
+    pub fn jumps(&self) -> usize {
+        self.route.len().saturating_sub(1)
+    }
+
+    /// The fuel cost spread over `units` units of cargo
+    ///
+    /// Add this to a per-unit cost of goods manufactured to get the cost of delivering them.
+    pub fn cost_per_unit(&self, units: u32) -> Option<f32> {
+        self.fuel_cost
+            .filter(|_| units > 0)
+            .map(|cost| cost / units as f32)
+    }
+}
+
+/// Estimates how long it takes and how much fuel it burns to ship cargo from `origin` to
+/// `destination`
+///
+/// Places are anything [`StarMap::system_of`] understands.  The fuel isn't priced, and the return
+/// flight isn't included.
+pub fn estimate(
+    map: &StarMap,
+    origin: &str,
+    destination: &str,
+    cargo: Cargo,
+    ship: &ShipClass,
+) -> Result<ShippingEstimate> {
+    let route = map
+        .shortest_path(origin, destination)
+        .ok_or_else(|| Error::MissingData(format!("No route from {origin} to {destination}")))?;
+    let distance: f64 = route
+        .windows(2)
+        .filter_map(|w| map.distance(&w[0], &w[1]))
+        .sum();
+    let stl_legs = if route.len() > 1 { 2.0 } else { 1.0 };
+
+    let trips = (cargo.weight / ship.max_weight)
+        .max(cargo.volume / ship.max_volume)
+        .ceil()
+        .max(1.0);
+    let mass_per_trip = (ship.empty_mass + cargo.weight / trips) / 1000.0;
+
+    let hours = stl_legs * ship.stl_hours_per_leg + distance as f32 * ship.ftl_hours_per_parsec;
+    Ok(ShippingEstimate {
+        route,
+        distance,
+        trips: trips as u32,
+        flight_time: Duration::from_secs_f32(hours * 3600.0),
+        stl_fuel: stl_legs * ship.stl_fuel_per_leg * mass_per_trip * trips,
+        ftl_fuel: distance as f32 * ship.ftl_fuel_per_parsec * mass_per_trip * trips,
+        fuel_cost: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::starmap::{StarSystem, SystemConnection};
+
+    #[test]
+    fn test_estimate() {
+        let system = |id: &str, x: f64, conn: Option<&str>| StarSystem {
+            system_id: id.to_string(),
+            name: id.to_string(),
+            natural_id: id.to_string(),
+            x,
+            y: 0.0,
+            z: 0.0,
+            connections: conn
+                .map(|c| SystemConnection {
+                    connecting_id: c.to_string(),
+                })
+                .into_iter()
+                .collect(),
+        };
+        let map = StarMap::new(
+            vec![
+                system("AA-001", 0.0, Some("BB-002")),
+                system("BB-002", 10.0, None),
+            ],
+            Vec::new(),
+            &[],
+        );
+
+        let cargo = Cargo::from_materials([("H2O", 1000)]).unwrap();
+        assert!((cargo.weight - 200.0).abs() < 1e-3);
+        assert!((cargo.volume - 200.0).abs() < 1e-3);
+
+        let ship = ShipClass::STANDARD;
+        let est = estimate(&map, "AA-001a", "BB-002c", cargo, &ship).unwrap();
+        assert_eq!(est.jumps(), 1);
+        assert_eq!(est.trips, 1);
+        assert_eq!(est.flight_time, Duration::from_secs(13 * 3600));
+        assert!((est.stl_fuel - 2.0 * 40.0 * 1.2).abs() < 1e-3);
+        assert!((est.ftl_fuel - 10.0 * 0.5 * 1.2).abs() < 1e-3);
+
+        let big = Cargo {
+            weight: 100.0,
+            volume: 1200.0,
+        };
+        assert_eq!(
+            estimate(&map, "AA-001", "AA-001", big, &ship)
+                .unwrap()
+                .trips,
+            3
+        );
+        assert!(estimate(&map, "AA-001", "ZZ-999", big, &ship).is_err());
+    }
+}
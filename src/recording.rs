@@ -0,0 +1,177 @@
+use std::path::{Path, PathBuf};
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{cache, Error, Result};
+
+/// Whether a [`FIOClient`](crate::FIOClient) talks to FIO, records what it gets, or replays recordings
+///
+/// Recordings are stored as one JSON fixture per URL, including the HTTP status, so a replaying
+/// client sees exactly the same 204s and 404s as the recording one did.  Auth tokens are never
+/// written to fixtures.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum HttpMode {
+    /// Send every request to FIO
+    #[default]
+    Live,
+    /// Send every request to FIO and write each response to a fixture in this directory
+    Record(PathBuf),
+    /// Never touch the network, and serve every response from the fixtures in this directory
+    Replay(PathBuf),
+}
+
+/// Status and body of a GET request, either from FIO or from a fixture
+pub(crate) struct RawResponse {
+    pub status: StatusCode,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+impl RawResponse {
+    pub fn is_json(&self) -> bool {
+        self.content_type
+            .as_deref()
+            .is_some_and(|ct| ct.starts_with("application/json"))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Fixture {
+    url: String,
+    status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    /// The body, if FIO sent it as JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    json: Option<serde_json::Value>,
+    /// The body, if it was anything else (like the plain text username from `/auth`) or didn't parse
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+}
+
+/// Fixtures are named like disk cache entries, which gives every URL its own file
+fn fixture_path(dir: &Path, url: &str) -> PathBuf {
+    dir.join(cache::file_name(url))
+}
+
+pub(crate) fn record(dir: &Path, url: &str, resp: &RawResponse) -> Result<()> {
+    // going by the content type, so a plain text body that happens to parse is replayed as is
+    let json = resp
+        .is_json()
+        .then(|| serde_json::from_slice(&resp.body).ok())
+        .flatten();
+    let text = match json {
+        None if !resp.body.is_empty() => Some(String::from_utf8_lossy(&resp.body).into_owned()),
+        _ => None,
+    };
+    let fixture = Fixture {
+        url: url.to_string(),
+        status: resp.status.as_u16(),
+        content_type: resp.content_type.clone(),
+        json,
+        text,
+    };
+
+    std::fs::create_dir_all(dir)?;
+    let file = std::fs::File::create(fixture_path(dir, url))?;
+    serde_json::to_writer_pretty(file, &fixture).map_err(|e| Error::Io(e.into()))
+}
+
+pub(crate) fn replay(dir: &Path, url: &str) -> Result<RawResponse> {
+    let path = fixture_path(dir, url);
+    if !path.exists() {
+        return Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("No recorded response for {url} at {}", path.display()),
+        )));
+    }
+    let (recorded, resp) = read_fixture(&path)?;
+    if recorded != url {
+        return Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} was recorded for {recorded}, not {url}", path.display()),
+        )));
+    }
+    Ok(resp)
+}
+
+/// Reads a fixture file, returning the URL it was recorded for and the response
+pub(crate) fn read_fixture(path: &Path) -> Result<(String, RawResponse)> {
+    let file = std::fs::File::open(path)?;
+    let fixture: Fixture = serde_json::from_reader(std::io::BufReader::new(file))
+        .map_err(Error::decode(&path.to_string_lossy()))?;
+    let url = fixture.url;
+    let content_type = fixture.content_type.or_else(|| {
+        fixture
+            .json
+            .is_some()
+            .then(|| "application/json".to_string())
+    });
+
+    let body = match (fixture.json, fixture.text) {
+        (Some(json), _) => serde_json::to_vec(&json).map_err(Error::decode(&url))?,
+        (None, Some(text)) => text.into_bytes(),
+        (None, None) => Vec::new(),
+    };
+    let status = StatusCode::from_u16(fixture.status).map_err(Error::decode(&url))?;
+    Ok((
+        url,
+        RawResponse {
+            status,
+            content_type,
+            body,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_replay() {
+        let dir = std::env::temp_dir().join(format!("fiors_fixtures_{}", std::process::id()));
+
+        let json = Some("application/json; charset=utf-8");
+        let text = Some("text/plain");
+        let cases = [
+            (
+                "/exchange/RAT.CI1",
+                200,
+                json,
+                br#"{"Ticker":"RAT"}"#.to_vec(),
+            ),
+            ("/exchange/full", 200, json, br#"[{"Ticker":"#.to_vec()),
+            ("/auth", 200, text, b"eminence32".to_vec()),
+            // a username that looks like JSON is still replayed as sent
+            ("/auth/other", 200, text, b" 12.50".to_vec()),
+            ("/localmarket/planet/UV-351a", 204, None, Vec::new()),
+        ];
+        for (url, status, content_type, body) in &cases {
+            let resp = RawResponse {
+                status: StatusCode::from_u16(*status).unwrap(),
+                content_type: content_type.map(str::to_string),
+                body: body.clone(),
+            };
+            record(&dir, url, &resp).unwrap();
+        }
+        for (url, status, _, body) in &cases {
+            let resp = replay(&dir, url).unwrap();
+            assert_eq!(resp.status.as_u16(), *status);
+            assert_eq!(&resp.body, body);
+        }
+        assert!(replay(&dir, "/planet/missing").is_err());
+        assert!(replay(&dir, "/exchange/RAT_CI1").is_err());
+
+        // a fixture recorded for another URL is never replayed
+        std::fs::copy(
+            fixture_path(&dir, "/auth"),
+            fixture_path(&dir, "/planet/UV-351a"),
+        )
+        .unwrap();
+        assert!(replay(&dir, "/planet/UV-351a").is_err());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
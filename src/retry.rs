@@ -0,0 +1,265 @@
+use std::{
+    hash::{BuildHasher, Hasher},
+    time::{Duration, Instant},
+};
+
+use reqwest::{header::HeaderMap, RequestBuilder, StatusCode};
+use tracing::warn;
+
+use crate::{recording::RawResponse, Error, Result};
+
+/// Controls how a [`FIOClient`](crate::FIOClient) retries requests that FIO asked it to back off from
+///
+/// Rate limits (429) and Cloudflare gateway errors (522/525) are retried, as are JSON bodies that
+/// were cut off (which FIO sometimes sends for `/exchange/full`) and timeouts if `retry_timeouts`
+/// is set.  The delay between attempts starts at `initial_delay` and is multiplied
+/// by `backoff_factor` after every failed attempt, up to `max_delay`.  A `Retry-After` header from
+/// FIO takes precedence over the computed delay.
+///
+/// Once `max_attempts` requests have been made, or the next attempt would start after
+/// `max_elapsed`, the request fails with [`Error::RetriesExhausted`](crate::Error::RetriesExhausted).
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub max_attempts: u32,
+    /// Give up once this much time has passed since the first attempt
+    pub max_elapsed: Duration,
+    /// Delay before the first retry
+    pub initial_delay: Duration,
+    /// Upper bound for the computed delay between two attempts
+    pub max_delay: Duration,
+    /// How much the delay grows after each failed attempt
+    pub backoff_factor: f32,
+    /// Randomly vary each delay by up to this fraction (0.25 means +/- 25%)
+    pub jitter: f32,
+    /// Whether timed out requests are retried, or fail right away with [`Error::Timeout`](crate::Error::Timeout)
+    pub retry_timeouts: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            max_elapsed: Duration::from_secs(120),
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(15),
+            backoff_factor: 1.75,
+            jitter: 0.25,
+            retry_timeouts: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if a response with this status should be retried
+    pub(crate) fn is_retryable(status: StatusCode) -> bool {
+        matches!(status.as_u16(), 429 | 522 | 525)
+    }
+
+    pub(crate) fn start(&self) -> Backoff<'_> {
+        Backoff {
+            policy: self,
+            attempts: 0,
+            started: Instant::now(),
+            delay: self.initial_delay,
+        }
+    }
+}
+
+/// The retry state of a single request
+///
+/// Each request gets its own `Backoff`, so concurrent requests don't slow each other down.
+pub(crate) struct Backoff<'a> {
+    policy: &'a RetryPolicy,
+    attempts: u32,
+    started: Instant,
+    delay: Duration,
+}
+
+impl Backoff<'_> {
+    /// Call this before every attempt
+    pub(crate) fn attempt(&mut self) {
+        self.attempts += 1;
+    }
+
+    /// Number of attempts made so far
+    pub(crate) fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Sleeps until the next attempt should be made
+    ///
+    /// Returns false without sleeping if the policy doesn't allow another attempt.
+    pub(crate) async fn wait(&mut self, retry_after: Option<Duration>) -> bool {
+        if self.attempts >= self.policy.max_attempts {
+            return false;
+        }
+
+        let sleep = retry_after.unwrap_or_else(|| jittered(self.delay, self.policy.jitter));
+        if self.started.elapsed() + sleep > self.policy.max_elapsed {
+            return false;
+        }
+        warn!(attempts = self.attempts, ?sleep, "Retrying request");
+        tokio::time::sleep(sleep).await;
+
+        self.delay = self
+            .delay
+            .mul_f32(self.policy.backoff_factor)
+            .min(self.policy.max_delay);
+        true
+    }
+}
+
+/// Sends the request built by `make_request` and reads its body, retrying according to `policy`
+///
+/// Returns the first response that shouldn't be retried, whatever its status.
+pub(crate) async fn send_with_retry(
+    policy: &RetryPolicy,
+    url: &str,
+    make_request: impl Fn() -> RequestBuilder,
+) -> Result<RawResponse> {
+    let mut backoff = policy.start();
+    loop {
+        backoff.attempt();
+        let retry_after = match send(make_request()).await {
+            Err(e) if e.is_timeout() && policy.retry_timeouts => {
+                warn!("Request timed out");
+                None
+            }
+            Err(e) => return Err(Error::from_request(url, e)),
+            Ok((resp, retry_after)) if RetryPolicy::is_retryable(resp.status) => {
+                warn!(status = %resp.status, "Request was not accepted");
+                retry_after
+            }
+            Ok((resp, _)) if is_truncated(&resp) => {
+                warn!("Response body was truncated");
+                None
+            }
+            Ok((resp, _)) => return Ok(resp),
+        };
+
+        if !backoff.wait(retry_after).await {
+            return Err(Error::RetriesExhausted {
+                url: url.to_string(),
+                attempts: backoff.attempts(),
+            });
+        }
+    }
+}
+
+/// Sends a request and reads the whole body, also returning the `Retry-After` header if any
+async fn send(
+    request: RequestBuilder,
+) -> std::result::Result<(RawResponse, Option<Duration>), reqwest::Error> {
+    let resp = request.send().await?;
+    let retry_after = retry_after(resp.headers());
+    let content_type = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .map(str::to_string);
+    let status = resp.status();
+    let body = resp.bytes().await?.into();
+    Ok((
+        RawResponse {
+            status,
+            content_type,
+            body,
+        },
+        retry_after,
+    ))
+}
+
+/// Returns true for a successful JSON response whose body ends before the JSON does
+fn is_truncated(resp: &RawResponse) -> bool {
+    resp.status.is_success()
+        && resp.is_json()
+        && !resp.body.is_empty()
+        && serde_json::from_slice::<serde::de::IgnoredAny>(&resp.body).is_err_and(|e| e.is_eof())
+}
+
+fn jittered(delay: Duration, jitter: f32) -> Duration {
+    if jitter <= 0.0 {
+        return delay;
+    }
+    // we don't need good randomness here, just enough to keep concurrent clients from retrying in lockstep
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    let unit = (hasher.finish() % 10_000) as f32 / 10_000.0; // [0, 1)
+    delay.mul_f32((1.0 + jitter * (2.0 * unit - 1.0)).max(0.0))
+}
+
+/// Parses a `Retry-After` header, which is either a number of seconds or an HTTP date
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(secs) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    (date.to_utc() - chrono::Utc::now()).to_std().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(reqwest::header::RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+
+        let later = chrono::Utc::now() + chrono::Duration::seconds(60);
+        headers.insert(
+            reqwest::header::RETRY_AFTER,
+            later.to_rfc2822().parse().unwrap(),
+        );
+        let d = retry_after(&headers).unwrap();
+        assert!(d > Duration::from_secs(55) && d <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_jitter() {
+        let base = Duration::from_millis(1000);
+        for _ in 0..100 {
+            let d = jittered(base, 0.25);
+            assert!(d >= Duration::from_millis(750) && d <= Duration::from_millis(1250));
+        }
+        assert_eq!(jittered(base, 0.0), base);
+    }
+
+    #[tokio::test]
+    async fn test_max_attempts() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let mut backoff = policy.start();
+        let mut attempts = 0;
+        loop {
+            backoff.attempt();
+            attempts += 1;
+            if !backoff.wait(None).await {
+                break;
+            }
+        }
+        assert_eq!(attempts, 3);
+        assert_eq!(backoff.attempts(), 3);
+    }
+}
@@ -0,0 +1,179 @@
+use std::{future::Future, sync::Arc};
+
+use dashmap::DashMap;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::{Error, Result};
+
+/// Deduplicates concurrent work for the same key
+///
+/// The first caller for a key runs the work, and everybody who shows up while it's running waits
+/// and gets a clone of its result.  A failure is shared too, so a failing request isn't repeated
+/// by every waiter; only the last one to get it sees the original error, the others get an
+/// [`Error::Shared`].  The result is dropped once the last waiter has it.
+pub(crate) struct SingleFlight<V> {
+    slots: DashMap<String, Slot<V>>,
+}
+
+type Slot<V> = Arc<Mutex<Option<Result<V, Arc<Error>>>>>;
+
+impl<V: Clone> SingleFlight<V> {
+    pub fn new() -> Self {
+        Self {
+            slots: DashMap::new(),
+        }
+    }
+
+    /// Runs `work`, or waits for the caller that's already running it for `key`
+    ///
+    /// The returned bool is true if this caller did the work itself.
+    pub async fn run<Fut>(&self, key: &str, work: impl FnOnce() -> Fut) -> Result<(V, bool)>
+    where
+        Fut: Future<Output = Result<V>>,
+    {
+        let slot = self.slots.entry(key.to_string()).or_default().clone();
+        let mut shared = slot.lock().await;
+        let result = match shared.as_ref() {
+            Some(Ok(v)) => Ok((v.clone(), false)),
+            Some(Err(e)) => Err(e.clone()),
+            None => {
+                let result = work().await.map_err(Arc::new);
+                *shared = Some(result.clone());
+                result.map(|v| (v, true))
+            }
+        };
+        drop(shared);
+        // once only the map and we hold the slot, nobody is waiting for it anymore, and later
+        // callers should start over.  Both sides of this hold the map's shard lock, so nobody can
+        // pick up the slot in between.
+        self.slots.remove_if(key, |_, s| {
+            Arc::ptr_eq(s, &slot) && Arc::strong_count(s) == 2
+        });
+        drop(slot);
+        result.map_err(|e| Arc::try_unwrap(e).unwrap_or_else(Error::Shared))
+    }
+}
+
+/// A set of async mutexes, one per key, that are cleaned up once nobody holds or waits for them
+pub(crate) struct KeyedLocks {
+    locks: Arc<DashMap<String, Arc<Mutex<()>>>>,
+}
+
+pub(crate) struct KeyedGuard {
+    key: String,
+    locks: Arc<DashMap<String, Arc<Mutex<()>>>>,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl KeyedLocks {
+    pub fn new() -> Self {
+        Self {
+            locks: Arc::new(DashMap::new()),
+        }
+    }
+
+    pub async fn lock(&self, key: &str) -> KeyedGuard {
+        let lock = self.locks.entry(key.to_string()).or_default().clone();
+        KeyedGuard {
+            key: key.to_string(),
+            locks: self.locks.clone(),
+            guard: Some(lock.lock_owned().await),
+        }
+    }
+}
+
+impl Drop for KeyedGuard {
+    fn drop(&mut self) {
+        self.guard.take();
+        // only the map holds on to the mutex anymore, so nobody is waiting for it
+        self.locks
+            .remove_if(&self.key, |_, lock| Arc::strong_count(lock) == 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_single_flight() {
+        let flight = Arc::new(SingleFlight::new());
+        let calls = Arc::new(AtomicU32::new(0));
+
+        let tasks: Vec<_> = (0..10)
+            .map(|_| {
+                let flight = flight.clone();
+                let calls = calls.clone();
+                tokio::spawn(async move {
+                    flight
+                        .run("/exchange/full", || async {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                            Ok(42)
+                        })
+                        .await
+                        .unwrap()
+                })
+            })
+            .collect();
+
+        let mut leaders = 0;
+        for task in tasks {
+            let (v, leader) = task.await.unwrap();
+            assert_eq!(v, 42);
+            leaders += leader as u32;
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(leaders, 1);
+        assert!(flight.slots.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_single_flight_error() {
+        let flight = Arc::new(SingleFlight::<u32>::new());
+        let calls = Arc::new(AtomicU32::new(0));
+
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let flight = flight.clone();
+                let calls = calls.clone();
+                tokio::spawn(async move {
+                    flight
+                        .run("/exchange/full", || async {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                            Err(Error::NotFound {
+                                url: "/exchange/full".into(),
+                            })
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert!(task.await.unwrap().unwrap_err().is_not_found());
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(flight.slots.is_empty());
+
+        // without anybody waiting, the caller gets the original error
+        let err = flight
+            .run("/exchange/full", || async { Err(Error::Auth) })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Auth));
+    }
+
+    #[tokio::test]
+    async fn test_keyed_locks() {
+        let locks = KeyedLocks::new();
+        let a = locks.lock("a").await;
+        let _b = locks.lock("b").await;
+        assert_eq!(locks.locks.len(), 2);
+        drop(a);
+        assert_eq!(locks.locks.len(), 1);
+    }
+}
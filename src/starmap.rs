@@ -0,0 +1,326 @@
+//! The star map: systems, the jump connections between them, and what's in them
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, VecDeque},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::Planet;
+
+/// A star system, as returned by `/systemstars`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct StarSystem {
+    pub system_id: String,
+    pub name: String,
+    /// Like "UV-351"
+    pub natural_id: String,
+    #[serde(rename = "PositionX")]
+    pub x: f64,
+    #[serde(rename = "PositionY")]
+    pub y: f64,
+    #[serde(rename = "PositionZ")]
+    pub z: f64,
+    #[serde(default)]
+    pub connections: Vec<SystemConnection>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SystemConnection {
+    /// The [`StarSystem::system_id`] at the other end
+    pub connecting_id: String,
+}
+
+/// A commodity exchange station, as returned by `/exchange/station`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ExchangeStation {
+    /// Like "ANT"
+    pub natural_id: String,
+    pub name: String,
+    /// Like "OT-580"
+    pub system_natural_id: String,
+    /// Like "CI1"
+    pub comex_code: String,
+    pub currency_code: Option<String>,
+}
+
+/// The system part of a planet's natural ID, like "UV-351" for "UV-351a"
+///
+/// Returns `None` if this doesn't look like a planet's natural ID.
+pub fn system_natural_id(planet_natural_id: &str) -> Option<&str> {
+    let system = planet_natural_id.trim_end_matches(|c: char| c.is_ascii_lowercase());
+    let valid = system.len() < planet_natural_id.len()
+        && system
+            .split_once('-')
+            .is_some_and(|(a, b)| !a.is_empty() && b.chars().all(|c| c.is_ascii_digit()));
+    valid.then_some(system)
+}
+
+/// All systems and the jump connections between them, indexed for route finding
+///
+/// Places can be given as system natural IDs ("UV-351"), planet natural IDs ("UV-351a"), planet
+/// names ("Montem"), or exchange station IDs ("ANT").
+///
+/// The map (indexes included) can be serialized, so it doesn't have to be rebuilt from the raw
+/// responses every time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StarMap {
+    /// By natural ID
+    pub systems: HashMap<String, StarSystem>,
+    pub stations: Vec<ExchangeStation>,
+    /// Map from system natural ID to the natural IDs of its planets
+    pub planets: HashMap<String, Vec<String>>,
+    /// Map from planet name to planet natural ID
+    planet_names: HashMap<String, String>,
+    /// Map from system natural ID to the natural IDs of the systems it connects to
+    neighbors: HashMap<String, Vec<String>>,
+}
+
+/// An entry of the priority queue in [`StarMap::shortest_path`], ordered so the closest is popped first
+struct Visit {
+    distance: f64,
+    system: String,
+}
+
+impl PartialEq for Visit {
+    fn eq(&self, other: &Self) -> bool {
+        self.distance.total_cmp(&other.distance) == Ordering::Equal
+    }
+}
+
+impl Eq for Visit {}
+
+impl PartialOrd for Visit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Visit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+impl StarMap {
+    pub fn new(
+        systems: Vec<StarSystem>,
+        stations: Vec<ExchangeStation>,
+        planets: &[Planet],
+    ) -> Self {
+        let by_id: HashMap<&str, &str> = systems
+            .iter()
+            .map(|s| (s.system_id.as_str(), s.natural_id.as_str()))
+            .collect();
+        let mut neighbors: HashMap<String, Vec<String>> = HashMap::new();
+        for system in &systems {
+            for conn in &system.connections {
+                if let Some(other) = by_id.get(conn.connecting_id.as_str()) {
+                    // connections are listed on both ends, but don't count on it
+                    for (a, b) in [
+                        (system.natural_id.as_str(), *other),
+                        (other, &system.natural_id),
+                    ] {
+                        let list = neighbors.entry(a.to_string()).or_default();
+                        if !list.iter().any(|n| n == b) {
+                            list.push(b.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut planets_by_system: HashMap<String, Vec<String>> = HashMap::new();
+        let mut planet_names = HashMap::new();
+        for planet in planets {
+            if let Some(system) = system_natural_id(&planet.natural_id) {
+                planets_by_system
+                    .entry(system.to_string())
+                    .or_default()
+                    .push(planet.natural_id.clone());
+            }
+            planet_names.insert(planet.name.clone(), planet.natural_id.clone());
+        }
+
+        Self {
+            systems: systems
+                .into_iter()
+                .map(|s| (s.natural_id.clone(), s))
+                .collect(),
+            stations,
+            planets: planets_by_system,
+            planet_names,
+            neighbors,
+        }
+    }
+
+    /// The natural ID of the system a place is in
+    pub fn system_of<'a>(&'a self, place: &'a str) -> Option<&'a str> {
+        if self.systems.contains_key(place) {
+            return Some(place);
+        }
+        if let Some(station) = self.stations.iter().find(|s| s.natural_id == place) {
+            return Some(&station.system_natural_id);
+        }
+        let natural_id = self.planet_names.get(place).map_or(place, String::as_str);
+        system_natural_id(natural_id).filter(|s| self.systems.contains_key(*s))
+    }
+
+    /// The natural IDs of the systems that are one jump away
+    pub fn neighbors(&self, system: &str) -> &[String] {
+        self.neighbors.get(system).map_or(&[], Vec::as_slice)
+    }
+
+    /// Straight-line distance between two systems, in parsecs
+    pub fn distance(&self, from: &str, to: &str) -> Option<f64> {
+        let a = self.systems.get(from)?;
+        let b = self.systems.get(to)?;
+        Some(((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt())
+    }
+
+    /// The smallest number of jumps between two places
+    ///
+    /// Returns `None` if either place is unknown or there's no route.
+    pub fn jump_count(&self, from: &str, to: &str) -> Option<usize> {
+        let from = self.system_of(from)?;
+        let to = self.system_of(to)?;
+        let mut jumps: HashMap<&str, usize> = HashMap::from([(from, 0)]);
+        let mut queue = VecDeque::from([from]);
+        while let Some(system) = queue.pop_front() {
+            let n = jumps[system];
+            if system == to {
+                return Some(n);
+            }
+            for next in self.neighbors(system) {
+                if !jumps.contains_key(next.as_str()) {
+                    jumps.insert(next, n + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// The shortest route by distance between two places, as a list of system natural IDs
+    /// including both ends
+    ///
+    /// Returns `None` if either place is unknown or there's no route.
+    pub fn shortest_path(&self, from: &str, to: &str) -> Option<Vec<String>> {
+        let from = self.system_of(from)?;
+        let to = self.system_of(to)?;
+
+        let mut best: HashMap<&str, f64> = HashMap::from([(from, 0.0)]);
+        let mut previous: HashMap<&str, &str> = HashMap::new();
+        let mut queue = BinaryHeap::from([Visit {
+            distance: 0.0,
+            system: from.to_string(),
+        }]);
+        while let Some(Visit { distance, system }) = queue.pop() {
+            if system == to {
+                let mut path = vec![to.to_string()];
+                let mut current = to;
+                while let Some(prev) = previous.get(current) {
+                    path.push(prev.to_string());
+                    current = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if best.get(system.as_str()).is_some_and(|b| distance > *b) {
+                continue;
+            }
+            let (system, _) = self.systems.get_key_value(&system)?;
+            for next in self.neighbors(system) {
+                let d = distance + self.distance(system, next).unwrap_or_default();
+                if best.get(next.as_str()).is_none_or(|b| d < *b) {
+                    best.insert(next, d);
+                    previous.insert(next, system);
+                    queue.push(Visit {
+                        distance: d,
+                        system: next.clone(),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// The exchange station with the fewest jumps from a place, and the number of jumps
+    pub fn nearest_exchange(&self, from: &str) -> Option<(&ExchangeStation, usize)> {
+        self.stations
+            .iter()
+            .filter_map(|s| Some((s, self.jump_count(from, &s.system_natural_id)?)))
+            .min_by_key(|(_, jumps)| *jumps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn system(id: &str, x: f64, connections: &[&str]) -> StarSystem {
+        StarSystem {
+            system_id: id.to_lowercase(),
+            name: id.to_string(),
+            natural_id: id.to_string(),
+            x,
+            y: 0.0,
+            z: 0.0,
+            connections: connections
+                .iter()
+                .map(|c| SystemConnection {
+                    connecting_id: c.to_lowercase(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_routes() {
+        assert_eq!(system_natural_id("UV-351a"), Some("UV-351"));
+        assert_eq!(system_natural_id("UV-351"), None);
+        assert_eq!(system_natural_id("Montem"), None);
+
+        // A - B - D is fewer jumps, A - C - E - D is shorter
+        let systems = vec![
+            system("AA-001", 0.0, &["BB-002", "CC-003"]),
+            system("BB-002", 50.0, &["DD-004"]),
+            system("CC-003", 1.0, &["EE-005"]),
+            system("EE-005", 2.0, &["DD-004"]),
+            system("DD-004", 3.0, &[]),
+            system("ZZ-999", 9.0, &[]),
+        ];
+        let stations = vec![ExchangeStation {
+            natural_id: "ANT".to_string(),
+            name: "Antares Station".to_string(),
+            system_natural_id: "DD-004".to_string(),
+            comex_code: "CI1".to_string(),
+            currency_code: Some("CIS".to_string()),
+        }];
+        let map = StarMap::new(systems, stations, &[]);
+
+        assert_eq!(map.system_of("AA-001b"), Some("AA-001"));
+        assert_eq!(map.system_of("ANT"), Some("DD-004"));
+        assert_eq!(map.jump_count("AA-001a", "ANT"), Some(2));
+        assert_eq!(map.jump_count("DD-004", "AA-001"), Some(2));
+        assert_eq!(map.jump_count("AA-001", "ZZ-999"), None);
+        assert_eq!(
+            map.shortest_path("AA-001a", "ANT").unwrap(),
+            ["AA-001", "CC-003", "EE-005", "DD-004"]
+        );
+        assert_eq!(map.shortest_path("AA-001", "AA-001").unwrap(), ["AA-001"]);
+
+        let (station, jumps) = map.nearest_exchange("BB-002").unwrap();
+        assert_eq!(station.comex_code, "CI1");
+        assert_eq!(jumps, 1);
+
+        let json = serde_json::to_string(&map).unwrap();
+        let map: StarMap = serde_json::from_str(&json).unwrap();
+        assert_eq!(map.jump_count("AA-001a", "ANT"), Some(2));
+        assert_eq!(map.neighbors("AA-001"), ["BB-002", "CC-003"]);
+    }
+}
@@ -0,0 +1,490 @@
+//! An in-process mock of the FIO REST API, for testing code that uses [`FIOClient`] without network access
+//!
+//! [`MockServer`] listens on localhost and serves JSON for the routes you give it, either one by one
+//! or from fixtures recorded with [`HttpMode::Record`](crate::HttpMode::Record).  Faults like rate
+//! limits or truncated bodies can be injected per route, and the server counts how often each route
+//! was hit, so retry and caching behavior can be checked.
+//!
+//! [`MockServer::start_with_defaults`] (or [`mock_client`]) also serves a small, consistent game
+//! world from the fixtures in `test_data/mock`: a planet, an exchange with a few tickers on CI1 and
+//! CI2, two star systems with a station each, and ships, storage and a company for `mockuser`.
+//!
+//! ```no_run
+//! # async fn example() -> fiors::Result<()> {
+//! use fiors::testing::{Fault, MockServer};
+//!
+//! let server = MockServer::start().await?;
+//! server.route("/exchange/full", serde_json::json!([
+//!     fiors::testing::ticker_json("RAT", "CI1", 40.0, 1000),
+//! ]));
+//! server.inject("/exchange/full", Fault::RateLimited { retry_after_secs: Some(0) });
+//!
+//! let client = server.client();
+//! let rat = client.get_exchange_info("RAT.CI1").await?;
+//! assert_eq!(rat.ask, Some(40.0));
+//! assert_eq!(server.hits("/exchange/full"), 2);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! This module is only available with the `testing` feature.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde_json::Value;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+use crate::{recording, FIOClient, FIOClientBuilder, Result, RetryPolicy};
+
+/// The API key that [`MockServer::client`] authenticates with
+pub const MOCK_API_KEY: &str = "mock-api-key";
+
+/// The fixtures [`MockServer::start_with_defaults`] serves
+const DEFAULT_FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_data/mock");
+
+/// Starts a server with the default routes and returns it together with a client pointed at it
+///
+/// The server shuts down when it's dropped, so keep it around for as long as the client is used.
+pub async fn mock_client() -> Result<(MockServer, FIOClient)> {
+    let server = MockServer::start_with_defaults().await?;
+    let client = server.client();
+    Ok((server, client))
+}
+
+/// A failure the mock server returns instead of the normal response, once per injected fault
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Answer with this HTTP status and an empty body
+    Status(u16),
+    /// Answer with 429 Too Many Requests, optionally with a `Retry-After` header
+    RateLimited { retry_after_secs: Option<u64> },
+    /// Answer with 204 No Content
+    NoContent,
+    /// Send only the first half of the normal body, like FIO sometimes does for `/exchange/full`
+    TruncatedBody,
+}
+
+struct Route {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+#[derive(Default)]
+struct State {
+    routes: HashMap<String, Route>,
+    faults: HashMap<String, VecDeque<Fault>>,
+    hits: HashMap<String, usize>,
+    /// Tokens that are accepted in the `Authorization` header
+    tokens: HashSet<String>,
+    /// If set, `/auth/login` only accepts this username and password
+    credentials: Option<(String, String)>,
+    token_lifetime: Option<chrono::Duration>,
+    logins: usize,
+}
+
+/// A local HTTP server that pretends to be FIO
+///
+/// The server shuts down when this is dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Starts a server on a random localhost port
+    ///
+    /// It accepts [`MOCK_API_KEY`] and any token handed out by `/auth/login`, and answers `/auth`
+    /// with the username `mockuser`.
+    pub async fn start() -> Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let addr = listener.local_addr()?;
+
+        let mut state = State::default();
+        state.tokens.insert(MOCK_API_KEY.to_string());
+        state.token_lifetime = Some(chrono::Duration::hours(24));
+        let state = Arc::new(Mutex::new(state));
+
+        let task = tokio::spawn({
+            let state = state.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(handle_connection(stream, state.clone()));
+                }
+            }
+        });
+
+        let server = Self { addr, state, task };
+        server.route_text("/auth", "mockuser");
+        Ok(server)
+    }
+
+    /// Starts a server that serves the default routes from `test_data/mock`
+    ///
+    /// Routes added afterwards replace the defaults for their path.
+    pub async fn start_with_defaults() -> Result<Self> {
+        let server = Self::start().await?;
+        server.load_fixtures(DEFAULT_FIXTURES)?;
+        Ok(server)
+    }
+
+    /// The base URL to point a client at
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// A builder pointed at this server, with a retry policy that doesn't wait long
+    pub fn builder(&self) -> FIOClientBuilder {
+        FIOClient::builder()
+            .base_url(self.base_url())
+            .timeout(Duration::from_secs(10))
+            .retry_policy(RetryPolicy {
+                initial_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(10),
+                jitter: 0.0,
+                ..Default::default()
+            })
+    }
+
+    /// A client pointed at this server that authenticates with [`MOCK_API_KEY`]
+    pub fn client(&self) -> FIOClient {
+        self.builder()
+            .build_with_key(MOCK_API_KEY)
+            .expect("Failed to build mock client")
+    }
+
+    /// Serves `json` for GET requests to `path`
+    pub fn route(&self, path: &str, json: Value) {
+        let body = serde_json::to_vec(&json).expect("Failed to serialize route");
+        self.set_route(path, 200, "application/json", body);
+    }
+
+    /// Serves plain text for GET requests to `path`
+    pub fn route_text(&self, path: &str, text: &str) {
+        self.set_route(path, 200, "text/plain", text.as_bytes().to_vec());
+    }
+
+    /// Answers GET requests to `path` with a status and an empty body, like 204 or 404
+    pub fn route_status(&self, path: &str, status: u16) {
+        self.set_route(path, status, "application/json", Vec::new());
+    }
+
+    fn set_route(&self, path: &str, status: u16, content_type: &'static str, body: Vec<u8>) {
+        self.state.lock().unwrap().routes.insert(
+            path.to_string(),
+            Route {
+                status,
+                content_type,
+                body,
+            },
+        );
+    }
+
+    /// Serves every fixture in a directory recorded with [`HttpMode::Record`](crate::HttpMode::Record)
+    pub fn load_fixtures(&self, dir: impl AsRef<Path>) -> Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let (url, resp) = recording::read_fixture(&path)?;
+            let content_type = if resp.content_type.is_some_and(|ct| ct.contains("json")) {
+                "application/json"
+            } else {
+                "text/plain"
+            };
+            self.set_route(&url, resp.status.as_u16(), content_type, resp.body);
+        }
+        Ok(())
+    }
+
+    /// Makes the next request to `path` fail with `fault`
+    ///
+    /// Faults queue up, so injecting two faults fails the next two requests.
+    pub fn inject(&self, path: &str, fault: Fault) {
+        self.state
+            .lock()
+            .unwrap()
+            .faults
+            .entry(path.to_string())
+            .or_default()
+            .push_back(fault);
+    }
+
+    /// How many requests were made to `path`, including failed ones
+    pub fn hits(&self, path: &str) -> usize {
+        let state = self.state.lock().unwrap();
+        state.hits.get(path).copied().unwrap_or_default()
+    }
+
+    /// Only accept this username and password in `/auth/login`.  By default, any login succeeds
+    pub fn set_credentials(&self, username: &str, password: &str) {
+        self.state.lock().unwrap().credentials = Some((username.to_string(), password.to_string()));
+    }
+
+    /// How long tokens handed out by `/auth/login` are valid, as reported in their `Expiry`
+    pub fn set_token_lifetime(&self, lifetime: chrono::Duration) {
+        self.state.lock().unwrap().token_lifetime = Some(lifetime);
+    }
+
+    /// Rejects every token handed out so far with 401, as if they had expired
+    pub fn revoke_tokens(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.tokens.retain(|t| t == MOCK_API_KEY);
+    }
+
+    /// How many successful logins there were
+    pub fn logins(&self) -> usize {
+        self.state.lock().unwrap().logins
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Builds a minimal `/exchange` ticker with a single sell and buy order around `price`
+///
+/// Useful for filling `/exchange/full` with just the materials a test needs.
+pub fn ticker_json(material: &str, exchange: &str, price: f32, supply: u32) -> Value {
+    serde_json::json!({
+        "MaterialTicker": material,
+        "ExchangeCode": exchange,
+        "Currency": "CIS",
+        "Price": price,
+        "Ask": price,
+        "Bid": price * 0.9,
+        "High": price,
+        "Low": price * 0.9,
+        "Supply": supply,
+        "Demand": supply,
+        "Traded": 0,
+        "MMBuy": null,
+        "MMSell": null,
+        "SellingOrders": [{
+            "CompanyCode": "MOCK",
+            "CompanyName": "Mock Company",
+            "ItemCost": price,
+            "ItemCount": supply,
+        }],
+        "BuyingOrders": [{
+            "CompanyCode": "MOCK",
+            "CompanyName": "Mock Company",
+            "ItemCost": price * 0.9,
+            "ItemCount": supply,
+        }],
+    })
+}
+
+/// Builds a minimal `/planet` response for a planet named "Mock Planet"
+pub fn planet_json(natural_id: &str) -> Value {
+    serde_json::json!({
+        "PlanetName": "Mock Planet",
+        "PlanetNaturalId": natural_id,
+        "PlanetId": "mockplanetid",
+        "HasLocalMarket": true,
+        "LocalMarketFeeFactor": 1.0,
+        "FactionCode": "CI",
+        "CurrencyCode": "CIS",
+        "HasWarehouse": false,
+        "Resources": [],
+        "ProductionFees": [],
+    })
+}
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<Option<Request>> {
+    let mut buf = Vec::new();
+    let header_end = loop {
+        let mut chunk = [0; 4096];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => content_length = value.trim().parse().unwrap_or(0),
+            "authorization" => authorization = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+
+    let mut body = buf[header_end..].to_vec();
+    while body.len() < content_length {
+        let mut chunk = [0; 4096];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+
+    Ok(Some(Request {
+        method,
+        path,
+        authorization,
+        body,
+    }))
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn empty(status: u16) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+}
+
+fn respond(state: &Mutex<State>, req: &Request) -> Response {
+    let mut state = state.lock().unwrap();
+    *state.hits.entry(req.path.clone()).or_default() += 1;
+
+    if let Some(fault) = state
+        .faults
+        .get_mut(&req.path)
+        .and_then(|faults| faults.pop_front())
+    {
+        match fault {
+            Fault::Status(status) => return Response::empty(status),
+            Fault::RateLimited { retry_after_secs } => {
+                let mut resp = Response::empty(429);
+                if let Some(secs) = retry_after_secs {
+                    resp.headers.push(("Retry-After", secs.to_string()));
+                }
+                return resp;
+            }
+            Fault::NoContent => return Response::empty(204),
+            Fault::TruncatedBody => {
+                let mut resp = route_response(&state, req);
+                let len = resp.body.len() / 2;
+                resp.body.truncate(len);
+                return resp;
+            }
+        }
+    }
+
+    if req.method == "POST" && req.path == "/auth/login" {
+        return login(&mut state, req);
+    }
+
+    let authorized = req
+        .authorization
+        .as_ref()
+        .is_some_and(|token| state.tokens.contains(token));
+    if !authorized {
+        return Response::empty(401);
+    }
+    route_response(&state, req)
+}
+
+fn route_response(state: &State, req: &Request) -> Response {
+    match state.routes.get(&req.path) {
+        Some(route) if req.method == "GET" => Response {
+            status: route.status,
+            content_type: route.content_type,
+            headers: Vec::new(),
+            body: route.body.clone(),
+        },
+        Some(_) => Response::empty(405),
+        None => Response::empty(404),
+    }
+}
+
+fn login(state: &mut State, req: &Request) -> Response {
+    let Ok(body) = serde_json::from_slice::<Value>(&req.body) else {
+        return Response::empty(400);
+    };
+    if let Some((username, password)) = &state.credentials {
+        if body["UserName"] != *username || body["Password"] != *password {
+            return Response::empty(401);
+        }
+    }
+
+    state.logins += 1;
+    let token = format!("mock-token-{}", state.logins);
+    state.tokens.insert(token.clone());
+    let expiry = chrono::Utc::now() + state.token_lifetime.unwrap_or_default();
+    Response {
+        status: 200,
+        content_type: "application/json",
+        headers: Vec::new(),
+        body: serde_json::to_vec(&serde_json::json!({
+            "AuthToken": token,
+            "Expiry": expiry.to_rfc3339(),
+        }))
+        .unwrap_or_default(),
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, state: Arc<Mutex<State>>) {
+    let Ok(Some(req)) = read_request(&mut stream).await else {
+        return;
+    };
+    let resp = respond(&state, &req);
+
+    let reason = reqwest::StatusCode::from_u16(resp.status)
+        .ok()
+        .and_then(|s| s.canonical_reason())
+        .unwrap_or("Unknown");
+    let mut head = format!(
+        "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        resp.status,
+        resp.content_type,
+        resp.body.len()
+    );
+    for (name, value) in &resp.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(&resp.body).await;
+    let _ = stream.shutdown().await;
+}
@@ -1,7 +1,8 @@
 use std::{collections::HashMap, ops::AddAssign, str::FromStr, time::Duration};
 
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Deserializer};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use serde_json::value::RawValue;
 use tracing::trace;
 
 use crate::{building_db::StaticBuildingInfo, get_building_db};
@@ -225,7 +226,7 @@ impl ProductionCategory {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
 pub enum Workforce {
     Pioneers,
     Settlers,
@@ -259,7 +260,8 @@ impl Workforce {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "TickerJson")]
 pub struct Ticker {
     /// The full ticker name like "SF.CI1"
     pub name: String,
@@ -325,68 +327,249 @@ impl Ticker {
     /// Returns the total cost to buy `quantity` units of this ticker
     ///
     /// Returns `None` if there are not enough sell orders to buy the requested quantity
-    pub fn instant_buy(&self, mut quantity: u32) -> Option<InstantOrder> {
-        // sort the sell orders, with lowest orders at the end
-        let mut orders = self.selling_orders.clone();
-        orders.sort_by(|a, b| a.item_cost.partial_cmp(&b.item_cost).unwrap());
-        orders.reverse();
-
-        let mut price = 0.0;
-        while let Some(order) = orders.pop() {
-            let Some(item_count) = order.item_count else {
-                // this is a market maker with unlimited stock, so it can
-                // fullfill the entire order
-                price += order.item_cost * quantity as f32;
-                return Some(InstantOrder {
-                    total_value: price,
-                    price_limit: order.item_cost,
-                });
-            };
-            if item_count >= quantity {
-                price += order.item_cost * quantity as f32;
-                return Some(InstantOrder {
-                    total_value: price,
-                    price_limit: order.item_cost,
-                });
-            } else {
-                price += order.item_cost * item_count as f32;
-                quantity -= item_count;
-            }
+    pub fn instant_buy(&self, quantity: u32) -> Option<InstantOrder> {
+        self.order_book().instant_buy(quantity)
+    }
+
+    /// Returns the total value of instantly selling `quantity` units of this ticker
+    ///
+    /// Returns `None` if there are not enough buy orders to sell the requested quantity
+    pub fn instant_sell(&self, quantity: u32) -> Option<InstantOrder> {
+        self.order_book().instant_sell(quantity)
+    }
+
+    /// What it costs to buy `quantity` units, even if the order book can't fill all of them
+    ///
+    /// Whatever the book can't cover is priced at the worst price paid for the rest, or at
+    /// [`get_any_price`](Self::get_any_price) if there are no sell orders.  Returns `None` if there
+    /// is no price at all.
+    pub fn buy_cost(&self, quantity: u32) -> Option<f32> {
+        let fill = self.order_book().fill_buy(quantity, true);
+        if fill.is_complete() {
+            return Some(fill.total_value);
         }
+        let fallback = fill.worst_price.or_else(|| self.get_any_price())?;
+        Some(fill.total_with_fallback(fallback))
+    }
+
+    /// The buy and sell orders of this ticker, sorted best price first
+    ///
+    /// Sorting is the expensive part, so keep the book around when pricing many quantities.
+    pub fn order_book(&self) -> OrderBook {
+        OrderBook::new(self.buying_orders.clone(), self.selling_orders.clone())
+    }
+}
+
+/// How much of an instant buy or sell the order book can fill, see [`OrderBook::fill_buy`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InstantFill {
+    /// Units that can be bought or sold right away
+    pub filled: u32,
+    /// Units left over once the book runs out
+    pub unfilled: u32,
+    /// The (price per unit, units) taken at each price level, best price first
+    pub levels: Vec<(f32, u32)>,
+    /// The total value of the filled units
+    pub total_value: f32,
+    /// The price of the last level that was touched, which is the price limit you'd need
+    pub worst_price: Option<f32>,
+}
+
+impl InstantFill {
+    pub fn is_complete(&self) -> bool {
+        self.unfilled == 0
+    }
+
+    /// The average price per filled unit
+    pub fn average_price(&self) -> Option<f32> {
+        (self.filled > 0).then(|| self.total_value / self.filled as f32)
+    }
+
+    /// The total value, with the unfilled units priced at `fallback` per unit
+    pub fn total_with_fallback(&self, fallback: f32) -> f32 {
+        self.total_value + fallback * self.unfilled as f32
+    }
+}
 
-        None
-    }
-
-    /// Returns
-    pub fn instant_sell(&self, mut quantity: u32) -> Option<InstantOrder> {
-        // sort the buy orders, with highest orders at the end
-        let mut orders = self.buying_orders.clone();
-        orders.sort_by(|a, b| a.item_cost.partial_cmp(&b.item_cost).unwrap());
-
-        let mut price = 0.0;
-        while let Some(order) = orders.pop() {
-            let Some(item_count) = order.item_count else {
-                // this is a market maker with unlimited stock, so it can
-                // fullfill the entire order
-                price += order.item_cost * quantity as f32;
-                return Some(InstantOrder {
-                    total_value: price,
-                    price_limit: order.item_cost,
-                });
+/// A point of [`OrderBook::impact_curve`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ImpactPoint {
+    pub quantity: u32,
+    /// The average price per unit for the whole quantity
+    pub average_price: f32,
+    /// How far the average price is from the best price, as a fraction (0.05 is 5% worse)
+    pub impact: f32,
+}
+
+/// The orders of a ticker, sorted so the best prices come first
+///
+/// Market makers (orders without an [`item_count`](MarketOrder::item_count)) can fill any
+/// quantity, but aren't counted in depth or volume figures.
+#[derive(Debug, Clone, Default)]
+pub struct OrderBook {
+    /// Buy orders, highest price first
+    pub bids: Vec<MarketOrder>,
+    /// Sell orders, lowest price first
+    pub asks: Vec<MarketOrder>,
+}
+
+impl OrderBook {
+    pub fn new(mut bids: Vec<MarketOrder>, mut asks: Vec<MarketOrder>) -> Self {
+        bids.sort_by(|a, b| b.item_cost.total_cmp(&a.item_cost));
+        asks.sort_by(|a, b| a.item_cost.total_cmp(&b.item_cost));
+        Self { bids, asks }
+    }
+
+    pub fn best_bid(&self) -> Option<f32> {
+        self.bids.first().map(|o| o.item_cost)
+    }
+
+    pub fn best_ask(&self) -> Option<f32> {
+        self.asks.first().map(|o| o.item_cost)
+    }
+
+    /// Halfway between the best bid and the best ask
+    pub fn mid_price(&self) -> Option<f32> {
+        Some((self.best_bid()? + self.best_ask()?) / 2.0)
+    }
+
+    /// The difference between the best ask and the best bid
+    pub fn spread(&self) -> Option<f32> {
+        Some(self.best_ask()? - self.best_bid()?)
+    }
+
+    /// The spread as a percentage of the mid price
+    pub fn spread_percent(&self) -> Option<f32> {
+        Some(100.0 * self.spread()? / self.mid_price()?)
+    }
+
+    /// Total units on the (bid, ask) side priced within `percent` % of the mid price
+    pub fn depth_within(&self, percent: f32) -> Option<(u32, u32)> {
+        let mid = self.mid_price()?;
+        let range = mid * percent / 100.0;
+        let units = |orders: &[MarketOrder]| {
+            orders
+                .iter()
+                .take_while(|o| (o.item_cost - mid).abs() <= range)
+                .filter_map(|o| o.item_count)
+                .sum()
+        };
+        Some((units(&self.bids), units(&self.asks)))
+    }
+
+    /// How lopsided the book is within `percent` % of the mid price, from -1 (only sellers) to 1
+    /// (only buyers)
+    pub fn imbalance(&self, percent: f32) -> Option<f32> {
+        let (bids, asks) = self.depth_within(percent)?;
+        let total = bids + asks;
+        (total > 0).then(|| (bids as f32 - asks as f32) / total as f32)
+    }
+
+    /// A rough measure of how much can be traded without moving the price: the value of the
+    /// orders within 5% of the mid price, discounted by the spread
+    pub fn liquidity_score(&self) -> Option<f32> {
+        let (bids, asks) = self.depth_within(5.0)?;
+        let value = (bids + asks) as f32 * self.mid_price()?;
+        Some(value / (1.0 + self.spread_percent()?))
+    }
+
+    fn fill(orders: &[MarketOrder], quantity: u32, include_market_makers: bool) -> InstantFill {
+        let mut fill = InstantFill {
+            unfilled: quantity,
+            ..Default::default()
+        };
+        for order in orders {
+            if fill.unfilled == 0 {
+                break;
+            }
+            let amount = match order.item_count {
+                Some(count) => count.min(fill.unfilled),
+                // a market maker has unlimited stock, so it can fill the rest of the order
+                None if include_market_makers => fill.unfilled,
+                None => continue,
             };
-            if item_count >= quantity {
-                price += order.item_cost * quantity as f32;
-                return Some(InstantOrder {
-                    total_value: price,
-                    price_limit: order.item_cost,
-                });
-            } else {
-                price += order.item_cost * item_count as f32;
-                quantity -= item_count;
+            if amount == 0 {
+                continue;
+            }
+            match fill.levels.last_mut() {
+                Some((price, units)) if *price == order.item_cost => *units += amount,
+                _ => fill.levels.push((order.item_cost, amount)),
             }
+            fill.filled += amount;
+            fill.unfilled -= amount;
+            fill.total_value += order.item_cost * amount as f32;
+            fill.worst_price = Some(order.item_cost);
+        }
+        fill
+    }
+
+    /// How much of `quantity` units can be bought from the asks right away, and at what prices
+    pub fn fill_buy(&self, quantity: u32, include_market_makers: bool) -> InstantFill {
+        Self::fill(&self.asks, quantity, include_market_makers)
+    }
+
+    /// How much of `quantity` units can be sold to the bids right away, and at what prices
+    pub fn fill_sell(&self, quantity: u32, include_market_makers: bool) -> InstantFill {
+        Self::fill(&self.bids, quantity, include_market_makers)
+    }
+
+    /// Turns a complete fill into an order, using the best price as the limit when nothing had to
+    /// be filled at all
+    fn complete(fill: InstantFill, orders: &[MarketOrder]) -> Option<InstantOrder> {
+        if !fill.is_complete() {
+            return None;
         }
+        Some(InstantOrder {
+            total_value: fill.total_value,
+            price_limit: fill
+                .worst_price
+                .or_else(|| orders.first().map(|o| o.item_cost))?,
+        })
+    }
+
+    /// What it costs to buy `quantity` units from the asks, or `None` if there aren't enough
+    pub fn instant_buy(&self, quantity: u32) -> Option<InstantOrder> {
+        Self::complete(self.fill_buy(quantity, true), &self.asks)
+    }
+
+    /// What we'd get for selling `quantity` units to the bids, or `None` if there aren't enough
+    pub fn instant_sell(&self, quantity: u32) -> Option<InstantOrder> {
+        Self::complete(self.fill_sell(quantity, true), &self.bids)
+    }
+
+    /// The volume-weighted average price per unit of buying (or selling) `quantity` units
+    pub fn vwap(&self, side: OrderType, quantity: u32) -> Option<f32> {
+        let order = match side {
+            OrderType::Buying => self.instant_buy(quantity),
+            OrderType::Selling => self.instant_sell(quantity),
+            OrderType::Other => None,
+        }?;
+        (quantity > 0).then(|| order.total_value / quantity as f32)
+    }
 
-        None
+    /// How the average price moves away from the best price as the quantity grows
+    ///
+    /// Quantities that can't be filled are left out.
+    pub fn impact_curve(&self, side: OrderType, quantities: &[u32]) -> Vec<ImpactPoint> {
+        let best = match side {
+            OrderType::Buying => self.best_ask(),
+            OrderType::Selling => self.best_bid(),
+            OrderType::Other => None,
+        };
+        let Some(best) = best.filter(|b| *b > 0.0) else {
+            return Vec::new();
+        };
+        quantities
+            .iter()
+            .filter_map(|&quantity| {
+                let average_price = self.vwap(side, quantity)?;
+                Some(ImpactPoint {
+                    quantity,
+                    average_price,
+                    impact: (average_price - best).abs() / best,
+                })
+            })
+            .collect()
     }
 }
 
@@ -411,36 +594,34 @@ pub struct MarketOrder {
     pub item_count: Option<u32>,
 }
 
-impl Ticker {
-    pub(crate) fn from_json(v: serde_json::Value) -> anyhow::Result<Self> {
-        #[derive(Deserialize)]
-        #[allow(unused)]
-        #[serde(rename_all = "PascalCase")]
-        struct Inner {
-            material_ticker: String,
-            exchange_code: String,
-            currency: String,
-            ask: Option<f32>,
-            bid: Option<f32>,
-
-            high: Option<f32>,
-            low: Option<f32>,
-            price: Option<f32>,
-            supply: u32,
-            demand: u32,
-            traded: u32,
-            #[serde(rename = "MMBuy")]
-            mm_buy: Option<f32>,
-            #[serde(rename = "MMSell")]
-            mm_sell: Option<f32>,
-
-            buying_orders: Vec<MarketOrder>,
-            selling_orders: Vec<MarketOrder>,
-        }
-
-        let inner: Inner = serde_json::from_value(v)?;
+/// The JSON representation of a [`Ticker`], as returned by `/exchange`
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TickerJson {
+    material_ticker: String,
+    exchange_code: String,
+    currency: String,
+    ask: Option<f32>,
+    bid: Option<f32>,
+
+    high: Option<f32>,
+    low: Option<f32>,
+    price: Option<f32>,
+    supply: u32,
+    demand: u32,
+    traded: u32,
+    #[serde(rename = "MMBuy")]
+    mm_buy: Option<f32>,
+    #[serde(rename = "MMSell")]
+    mm_sell: Option<f32>,
+
+    buying_orders: Vec<MarketOrder>,
+    selling_orders: Vec<MarketOrder>,
+}
 
-        Ok(Self {
+impl From<TickerJson> for Ticker {
+    fn from(inner: TickerJson) -> Self {
+        Self {
             name: format!("{}.{}", inner.material_ticker, inner.exchange_code),
             currency: inner.currency,
             ask: inner.ask,
@@ -455,8 +636,197 @@ impl Ticker {
             traded: inner.traded,
             mm_buy: inner.mm_buy,
             mm_sell: inner.mm_sell,
-        })
+        }
+    }
+}
+
+/// An entry of a bulk response that could not be parsed
+#[derive(Debug, Clone)]
+pub struct RejectedEntry {
+    /// Position of the entry in the response
+    pub index: usize,
+    /// Whatever identifies the entry, like a ticker name, if that much could be read
+    pub id: Option<String>,
+    /// Why the entry was rejected
+    pub reason: String,
+}
+
+/// All tickers of all exchanges, as returned by `/exchange/full`
+#[derive(Debug, Clone)]
+pub struct ExchangeSnapshot {
+    /// Map from full ticker name (like "SF.CI1") to ticker
+    pub tickers: HashMap<String, Ticker>,
+    /// Tickers that didn't match the expected format
+    pub rejected: Vec<RejectedEntry>,
+    pub fetched_at: DateTime<Utc>,
+}
+
+impl ExchangeSnapshot {
+    /// Parses the entries of `/exchange/full` one by one, collecting the ones that don't parse
+    pub(crate) fn from_raw(entries: &[Box<RawValue>], fetched_at: DateTime<Utc>) -> Self {
+        let (tickers, rejected) = parse_entries::<Ticker>(entries, |v| {
+            match (v["MaterialTicker"].as_str(), v["ExchangeCode"].as_str()) {
+                (Some(m), Some(e)) => Some(format!("{m}.{e}")),
+                (m, e) => m.or(e).map(str::to_string),
+            }
+        });
+
+        Self {
+            tickers: tickers.into_iter().map(|t| (t.name.clone(), t)).collect(),
+            rejected,
+            fetched_at,
+        }
+    }
+}
+
+/// The length of time a [`PriceCandle`] covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PriceInterval {
+    MinuteFive,
+    MinuteFifteen,
+    MinuteThirty,
+    HourOne,
+    HourTwo,
+    HourFour,
+    HourSix,
+    HourTwelve,
+    DayOne,
+    DayThree,
+    #[serde(other)]
+    Other,
+}
+
+/// Trading activity of a ticker during one interval
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PriceCandle {
+    pub interval: PriceInterval,
+    /// When this interval started
+    #[serde(rename = "DateEpochMs", deserialize_with = "ms_to_date")]
+    pub date: DateTime<Utc>,
+    pub open: f32,
+    pub close: f32,
+    pub high: f32,
+    pub low: f32,
+    /// The total value of all trades, in the exchange's currency
+    pub volume: f32,
+    /// The number of units traded
+    pub traded: u32,
+}
+
+/// Price history of a ticker, as returned by `/exchange/cxpc`
+#[derive(Debug, Clone)]
+pub struct PriceHistory {
+    /// Full ticker name, like "SF.CI1"
+    pub ticker: String,
+    /// Candles of all intervals, oldest first
+    pub candles: Vec<PriceCandle>,
+}
+
+impl PriceHistory {
+    pub fn new(ticker: impl ToString, mut candles: Vec<PriceCandle>) -> Self {
+        candles.sort_by_key(|c| c.date);
+        Self {
+            ticker: ticker.to_string(),
+            candles,
+        }
+    }
+
+    /// Returns the candles of one interval, oldest first
+    pub fn candles(&self, interval: PriceInterval) -> impl Iterator<Item = &PriceCandle> {
+        self.candles.iter().filter(move |c| c.interval == interval)
+    }
+
+    /// The daily candles that started in the `days` days before `now`, oldest first
+    ///
+    /// FIO has no candles for days without trades, so there can be fewer than `days` of them.
+    fn daily(&self, days: usize, now: DateTime<Utc>) -> impl Iterator<Item = &PriceCandle> {
+        let since = now - chrono::Duration::days(days as i64);
+        self.candles(PriceInterval::DayOne)
+            .filter(move |c| c.date >= since)
+    }
+
+    /// The average daily closing price over the `days` days before `now`
+    pub fn moving_average(&self, days: usize, now: DateTime<Utc>) -> Option<f32> {
+        let closes: Vec<f32> = self.daily(days, now).map(|c| c.close).collect();
+        if closes.is_empty() {
+            return None;
+        }
+        Some(closes.iter().sum::<f32>() / closes.len() as f32)
+    }
+
+    /// The volume-weighted average price over the `days` days before `now`
+    ///
+    /// Returns `None` if nothing was traded in that time.
+    pub fn vwap(&self, days: usize, now: DateTime<Utc>) -> Option<f32> {
+        let (volume, traded) = self
+            .daily(days, now)
+            .fold((0.0, 0), |(v, t), c| (v + c.volume, t + c.traded));
+        (traded > 0).then(|| volume / traded as f32)
+    }
+
+    /// The standard deviation of the day-to-day change of the closing price, over the `days` days
+    /// before `now`
+    ///
+    /// A volatility of 0.05 means the price typically moves by about 5% per day.
+    pub fn volatility(&self, days: usize, now: DateTime<Utc>) -> Option<f32> {
+        let closes: Vec<f32> = self.daily(days, now).map(|c| c.close).collect();
+        let returns: Vec<f32> = closes
+            .windows(2)
+            .filter(|w| w[0] > 0.0)
+            .map(|w| w[1] / w[0] - 1.0)
+            .collect();
+        if returns.is_empty() {
+            return None;
+        }
+        let mean = returns.iter().sum::<f32>() / returns.len() as f32;
+        let variance =
+            returns.iter().map(|r| (r - mean).powi(2)).sum::<f32>() / returns.len() as f32;
+        Some(variance.sqrt())
+    }
+}
+
+/// Which price to use when valuing materials bought from an exchange
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PriceBasis {
+    /// What it would cost to buy right now
+    #[default]
+    Spot,
+    /// The volume-weighted average price over this many days, falling back to the spot price if
+    /// the material wasn't traded in that time
+    Average { days: usize },
+}
+
+/// The parsed entries of a catalog endpoint like `/material/allmaterials`
+#[derive(Debug, Clone)]
+pub struct Catalog<T> {
+    pub entries: T,
+    /// Entries that didn't match the expected format
+    pub rejected: Vec<RejectedEntry>,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// Parses each entry of a bulk response on its own, so one bad entry doesn't fail all of them
+///
+/// `id` picks something to identify a rejected entry by.
+pub(crate) fn parse_entries<T: DeserializeOwned>(
+    entries: &[Box<RawValue>],
+    id: impl Fn(&serde_json::Value) -> Option<String>,
+) -> (Vec<T>, Vec<RejectedEntry>) {
+    let mut parsed = Vec::with_capacity(entries.len());
+    let mut rejected = Vec::new();
+    for (index, raw) in entries.iter().enumerate() {
+        match serde_json::from_str(raw.get()) {
+            Ok(entry) => parsed.push(entry),
+            Err(e) => rejected.push(RejectedEntry {
+                index,
+                id: serde_json::from_str(raw.get()).ok().as_ref().and_then(&id),
+                reason: e.to_string(),
+            }),
+        }
     }
+    (parsed, rejected)
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -490,6 +860,32 @@ pub struct PlanetSite {
     pub buildings: Vec<SiteBuilding>,
 }
 
+/// A planet that a user has a base on, as returned by [`FIOClient::get_planets_for_user`](crate::FIOClient::get_planets_for_user)
+#[derive(Debug, Clone)]
+pub struct SiteSummary {
+    pub planet_id: String,
+    /// The name of the form AB-123x
+    pub natural_id: String,
+    pub name: String,
+    pub has_local_market: bool,
+    pub has_warehouse: bool,
+    /// Default market/exchange for this planet, see [`Planet::get_cx_mid`]
+    pub cx_mid: Option<&'static str>,
+}
+
+impl From<&Planet> for SiteSummary {
+    fn from(planet: &Planet) -> Self {
+        Self {
+            planet_id: planet.id.clone(),
+            natural_id: planet.natural_id.clone(),
+            name: planet.name.clone(),
+            has_local_market: planet.has_local_market,
+            has_warehouse: planet.has_warehouse,
+            cx_mid: planet.get_cx_mid(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct PlanetResource {
@@ -499,6 +895,16 @@ pub struct PlanetResource {
     pub factor: f32,
 }
 
+impl PlanetResource {
+    /// The ticker of this resource's material, like "H2O"
+    pub fn ticker(&self) -> Option<&'static str> {
+        crate::get_material_db()
+            .values()
+            .find(|m| m.material_id == self.material_id)
+            .map(|m| m.ticker)
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct ProductionFee {
@@ -528,6 +934,38 @@ pub struct Planet {
 
     pub resources: Vec<PlanetResource>,
     pub production_fees: Vec<ProductionFee>,
+
+    /// Surface gravity, in g
+    #[serde(default)]
+    pub gravity: Option<f32>,
+    /// Atmospheric pressure, in atm
+    #[serde(default)]
+    pub pressure: Option<f32>,
+    /// Average temperature, in °C
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    /// True for rocky planets, false for gas giants
+    #[serde(default)]
+    pub surface: Option<bool>,
+    /// Between -1 and 1, where -1 means crops can't be grown at all
+    #[serde(default)]
+    pub fertility: Option<f32>,
+
+    /// Past, current and upcoming programs of the planet's Chamber of Global Commerce
+    #[serde(rename = "COGCPrograms", default)]
+    pub cogc_programs: Vec<CogcProgram>,
+}
+
+/// A program of a Chamber of Global Commerce, which gives a bonus to one industry or workforce
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CogcProgram {
+    /// Like "ADVERTISING_AGRICULTURE" or "WORKFORCE_PIONEERS", or `None` if no program was voted for
+    pub program_type: Option<String>,
+    #[serde(rename = "StartEpochMs", deserialize_with = "ms_to_date")]
+    pub start: DateTime<Utc>,
+    #[serde(rename = "EndEpochMs", deserialize_with = "ms_to_date")]
+    pub end: DateTime<Utc>,
 }
 
 impl Planet {
@@ -536,6 +974,87 @@ impl Planet {
 
         Ok(planet)
     }
+
+    /// The COGC program that's running at `now`
+    pub fn current_cogc_program(&self, now: DateTime<Utc>) -> Option<&CogcProgram> {
+        self.cogc_programs
+            .iter()
+            .find(|p| p.start <= now && now < p.end)
+    }
+
+    /// The next COGC program to start after `now`
+    pub fn upcoming_cogc_program(&self, now: DateTime<Utc>) -> Option<&CogcProgram> {
+        self.cogc_programs
+            .iter()
+            .filter(|p| p.start > now)
+            .min_by_key(|p| p.start)
+    }
+
+    pub fn is_fertile(&self) -> bool {
+        self.fertility.is_some_and(|f| f > -1.0)
+    }
+
+    /// Whether the gravity is low or high enough to need special building materials
+    pub fn gravity_level(&self) -> Option<EnvLevel> {
+        self.gravity.map(|g| EnvLevel::classify(g, 0.25, 2.5))
+    }
+
+    /// Whether the pressure is low or high enough to need special building materials
+    pub fn pressure_level(&self) -> Option<EnvLevel> {
+        self.pressure.map(|p| EnvLevel::classify(p, 0.25, 2.0))
+    }
+
+    /// Whether the temperature is low or high enough to need special building materials
+    pub fn temperature_level(&self) -> Option<EnvLevel> {
+        self.temperature.map(|t| EnvLevel::classify(t, -25.0, 75.0))
+    }
+
+    /// The full bill of materials to construct a building on this planet
+    ///
+    /// On top of [`StaticBuildingInfo::building_cost`], every building needs MCG (rocky planets) or
+    /// AEF (gas giants) for its area, and extreme environments need SEA, HSE, INS, TSH, MGC or BL.
+    /// Environment data that's missing is treated as normal conditions.
+    pub fn building_materials(&self, building: &StaticBuildingInfo) -> Vec<(&'static str, u32)> {
+        let area = building.area_cost;
+        let mut extra = Vec::new();
+        match self.surface {
+            Some(true) => extra.push(("MCG", area * 4)),
+            Some(false) => extra.push(("AEF", area.div_ceil(3))),
+            None => {}
+        }
+        match self.gravity_level() {
+            Some(EnvLevel::Low) => extra.push(("MGC", 1)),
+            Some(EnvLevel::High) => extra.push(("BL", 1)),
+            _ => {}
+        }
+        match self.pressure_level() {
+            Some(EnvLevel::Low) => extra.push(("SEA", area)),
+            Some(EnvLevel::High) => extra.push(("HSE", 1)),
+            _ => {}
+        }
+        match self.temperature_level() {
+            Some(EnvLevel::Low) => extra.push(("INS", area * 10)),
+            Some(EnvLevel::High) => extra.push(("TSH", 1)),
+            _ => {}
+        }
+
+        let mut materials = building.building_cost.to_vec();
+        for (ticker, amount) in extra {
+            match materials.iter_mut().find(|(t, _)| *t == ticker) {
+                Some((_, existing)) => *existing += amount,
+                None => materials.push((ticker, amount)),
+            }
+        }
+        materials
+    }
+
+    /// The factor of a resource on this planet, by material ticker
+    pub fn resource_factor(&self, ticker: &str) -> Option<f32> {
+        self.resources
+            .iter()
+            .find(|r| r.ticker() == Some(ticker))
+            .map(|r| r.factor)
+    }
     /// Get the default market/exchange code for this planet
     pub fn get_cx_mid(&self) -> Option<&'static str> {
         match self.currency_code.as_deref() {
@@ -555,6 +1074,274 @@ impl Planet {
     }
 }
 
+/// Population and infrastructure of a planet, as returned by `/infrastructure`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "InfrastructureJson")]
+pub struct PlanetInfrastructure {
+    /// Population of each workforce tier, from the latest population report
+    pub population: HashMap<Workforce, PopulationTier>,
+    pub projects: Vec<InfrastructureProject>,
+}
+
+/// One workforce tier of a planet's population
+#[derive(Debug, Clone, Default)]
+pub struct PopulationTier {
+    pub count: u32,
+    /// Between 0 and 1
+    pub unemployment_rate: f32,
+    pub open_jobs: f32,
+    /// Between 0 and 1
+    pub happiness: f32,
+}
+
+/// A planetary project, like a Planetary Administration Center or a Chamber of Global Commerce
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct InfrastructureProject {
+    /// Like "PAC" or "COGC"
+    pub ticker: String,
+    pub name: String,
+    /// The level it's being upgraded to, or its current level if it's not being upgraded
+    pub level: u32,
+    /// The level that's in effect
+    pub active_level: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InfrastructureJson {
+    #[serde(default)]
+    infrastructure_reports: Vec<PopulationReportJson>,
+    #[serde(default)]
+    infrastructure_projects: Vec<InfrastructureProject>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PopulationReportJson {
+    simulation_period: u32,
+    next_population_pioneer: u32,
+    next_population_settler: u32,
+    next_population_technician: u32,
+    next_population_engineer: u32,
+    next_population_scientist: u32,
+    unemployment_rate_pioneer: f32,
+    unemployment_rate_settler: f32,
+    unemployment_rate_technician: f32,
+    unemployment_rate_engineer: f32,
+    unemployment_rate_scientist: f32,
+    open_jobs_pioneer: f32,
+    open_jobs_settler: f32,
+    open_jobs_technician: f32,
+    open_jobs_engineer: f32,
+    open_jobs_scientist: f32,
+    average_happiness_pioneer: f32,
+    average_happiness_settler: f32,
+    average_happiness_technician: f32,
+    average_happiness_engineer: f32,
+    average_happiness_scientist: f32,
+}
+
+impl From<InfrastructureJson> for PlanetInfrastructure {
+    fn from(inner: InfrastructureJson) -> Self {
+        let mut population = HashMap::new();
+        if let Some(r) = inner
+            .infrastructure_reports
+            .into_iter()
+            .max_by_key(|r| r.simulation_period)
+        {
+            let tiers = [
+                (
+                    Workforce::Pioneers,
+                    r.next_population_pioneer,
+                    r.unemployment_rate_pioneer,
+                    r.open_jobs_pioneer,
+                    r.average_happiness_pioneer,
+                ),
+                (
+                    Workforce::Settlers,
+                    r.next_population_settler,
+                    r.unemployment_rate_settler,
+                    r.open_jobs_settler,
+                    r.average_happiness_settler,
+                ),
+                (
+                    Workforce::Technicians,
+                    r.next_population_technician,
+                    r.unemployment_rate_technician,
+                    r.open_jobs_technician,
+                    r.average_happiness_technician,
+                ),
+                (
+                    Workforce::Engineers,
+                    r.next_population_engineer,
+                    r.unemployment_rate_engineer,
+                    r.open_jobs_engineer,
+                    r.average_happiness_engineer,
+                ),
+                (
+                    Workforce::Scientists,
+                    r.next_population_scientist,
+                    r.unemployment_rate_scientist,
+                    r.open_jobs_scientist,
+                    r.average_happiness_scientist,
+                ),
+            ];
+            for (tier, count, unemployment_rate, open_jobs, happiness) in tiers {
+                population.insert(
+                    tier,
+                    PopulationTier {
+                        count,
+                        unemployment_rate,
+                        open_jobs,
+                        happiness,
+                    },
+                );
+            }
+        }
+
+        Self {
+            population,
+            projects: inner.infrastructure_projects,
+        }
+    }
+}
+
+impl PlanetInfrastructure {
+    /// The active level of a project, like "COGC", or 0 if it wasn't built
+    pub fn project_level(&self, ticker: &str) -> u32 {
+        self.projects
+            .iter()
+            .find(|p| p.ticker == ticker)
+            .map_or(0, |p| p.active_level)
+    }
+}
+
+/// How a planet's gravity, pressure or temperature compares to normal conditions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvLevel {
+    Low,
+    Normal,
+    High,
+}
+
+impl EnvLevel {
+    fn classify(value: f32, low: f32, high: f32) -> Self {
+        if value < low {
+            Self::Low
+        } else if value > high {
+            Self::High
+        } else {
+            Self::Normal
+        }
+    }
+}
+
+/// Filters for [`FIOClient::search_planets`](crate::FIOClient::search_planets)
+///
+/// The default matches every planet.
+///
+/// ```
+/// let query = fiors::types::PlanetQuery {
+///     include_gaseous: false,
+///     must_be_fertile: true,
+///     ..Default::default()
+/// }
+/// .resource("H2O", 0.2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PlanetQuery {
+    /// Material tickers that must be present, each with a minimum [`PlanetResource::factor`]
+    pub resources: Vec<(String, f32)>,
+    pub include_rocky: bool,
+    pub include_gaseous: bool,
+    pub include_low_gravity: bool,
+    pub include_high_gravity: bool,
+    pub include_low_pressure: bool,
+    pub include_high_pressure: bool,
+    pub include_low_temperature: bool,
+    pub include_high_temperature: bool,
+    pub must_be_fertile: bool,
+    pub must_have_local_market: bool,
+    pub must_have_warehouse: bool,
+    /// Like "CI", or `None` for any faction (including none)
+    pub faction: Option<String>,
+}
+
+impl Default for PlanetQuery {
+    fn default() -> Self {
+        Self {
+            resources: Vec::new(),
+            include_rocky: true,
+            include_gaseous: true,
+            include_low_gravity: true,
+            include_high_gravity: true,
+            include_low_pressure: true,
+            include_high_pressure: true,
+            include_low_temperature: true,
+            include_high_temperature: true,
+            must_be_fertile: false,
+            must_have_local_market: false,
+            must_have_warehouse: false,
+            faction: None,
+        }
+    }
+}
+
+impl PlanetQuery {
+    /// Requires a resource with at least this factor
+    pub fn resource(mut self, ticker: impl ToString, min_factor: f32) -> Self {
+        self.resources.push((ticker.to_string(), min_factor));
+        self
+    }
+
+    /// Whether a planet passes all filters
+    ///
+    /// Planets without environment data only pass environment filters that include everything.
+    pub fn matches(&self, planet: &Planet) -> bool {
+        let level_ok = |level: Option<EnvLevel>, low: bool, high: bool| match level {
+            Some(EnvLevel::Low) => low,
+            Some(EnvLevel::High) => high,
+            Some(EnvLevel::Normal) => true,
+            None => low && high,
+        };
+        let surface_ok = match planet.surface {
+            Some(true) => self.include_rocky,
+            Some(false) => self.include_gaseous,
+            None => self.include_rocky && self.include_gaseous,
+        };
+
+        surface_ok
+            && level_ok(
+                planet.gravity_level(),
+                self.include_low_gravity,
+                self.include_high_gravity,
+            )
+            && level_ok(
+                planet.pressure_level(),
+                self.include_low_pressure,
+                self.include_high_pressure,
+            )
+            && level_ok(
+                planet.temperature_level(),
+                self.include_low_temperature,
+                self.include_high_temperature,
+            )
+            && (!self.must_be_fertile || planet.is_fertile())
+            && (!self.must_have_local_market || planet.has_local_market)
+            && (!self.must_have_warehouse || planet.has_warehouse)
+            && self
+                .faction
+                .as_ref()
+                .is_none_or(|f| planet.faction_code.as_ref() == Some(f))
+            && self.resources.iter().all(|(ticker, min)| {
+                planet
+                    .resource_factor(ticker)
+                    .is_some_and(|factor| factor >= *min)
+            })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PlanetWorkforce {
     pub planet_id: String,
@@ -677,12 +1464,6 @@ pub struct MaterialInfo {
     pub volume: f32,
 }
 
-impl MaterialInfo {
-    pub(crate) fn from_json(v: serde_json::Value) -> anyhow::Result<Self> {
-        Ok(serde_json::from_value(v)?)
-    }
-}
-
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct ProductionLine {
@@ -863,12 +1644,6 @@ pub struct BuildingCost {
     pub commodity_ticker: String,
 }
 
-impl BuildingInfo {
-    pub(crate) fn from_json(v: serde_json::Value) -> anyhow::Result<Self> {
-        Ok(serde_json::from_value(v)?)
-    }
-}
-
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Recipe {
@@ -881,12 +1656,6 @@ pub struct Recipe {
     pub duration: Duration,
 }
 
-impl Recipe {
-    pub(crate) fn from_json(v: serde_json::Value) -> anyhow::Result<Self> {
-        Ok(serde_json::from_value(v)?)
-    }
-}
-
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct RecipeMaterial {
@@ -897,27 +1666,395 @@ pub struct RecipeMaterial {
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct OwnMarketOrder {
-    // pub trades: Vec<Trade>,
     pub exchange_code: String,
-    /// Either "BUYING" or "SELLING"
-    pub order_type: String,
+    pub order_type: OrderType,
     /// Note, this doesn't contain the full ticker, just the material name
     pub material_ticker: String,
+    /// The amount that's still open
     pub amount: u32,
     pub initial_amount: u32,
     pub limit: f32,
     pub limit_currency: String,
-    /// Either "PLACED" or "FILLED" or "PARTIALLY_FILLED"
-    pub status: String,
+    pub status: OrderStatus,
     #[serde(rename = "CreatedEpochMs", deserialize_with = "ms_to_date")]
     pub created: DateTime<Utc>,
+    /// Trades that (partially) filled this order, oldest first
+    #[serde(default)]
+    pub trades: Vec<Trade>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderType {
+    Buying,
+    Selling,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderStatus {
+    Placed,
+    PartiallyFilled,
+    Filled,
+    #[serde(other)]
+    Other,
 }
 
+/// An executed trade that filled (part of) an order
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Trade {
     pub amount: u32,
+    /// Price per unit
     pub price: f32,
+    #[serde(
+        rename = "TradeTimeEpochMs",
+        deserialize_with = "optional_ms_to_date",
+        default
+    )]
+    pub time: Option<DateTime<Utc>>,
+    /// Company code of the other side of the trade
+    #[serde(default)]
+    pub partner_code: Option<String>,
+}
+
+impl OwnMarketOrder {
+    /// How many units have been bought or sold so far
+    pub fn filled_amount(&self) -> u32 {
+        self.initial_amount.saturating_sub(self.amount)
+    }
+
+    /// The value of all trades so far: what we paid for a buy order, or got for a sell order
+    pub fn realized_value(&self) -> f32 {
+        self.trades.iter().map(|t| t.amount as f32 * t.price).sum()
+    }
+
+    /// The average price per unit of all trades so far
+    pub fn average_fill_price(&self) -> Option<f32> {
+        let amount: u32 = self.trades.iter().map(|t| t.amount).sum();
+        (amount > 0).then(|| self.realized_value() / amount as f32)
+    }
+
+    pub fn age(&self, now: DateTime<Utc>) -> chrono::Duration {
+        now - self.created
+    }
+
+    /// How much longer until the order is filled, if it keeps filling at the rate it has so far
+    ///
+    /// Returns `None` if nothing has been filled yet, or if the estimate is too far out to represent.
+    pub fn expected_time_to_fill(&self, now: DateTime<Utc>) -> Option<chrono::Duration> {
+        let filled = self.filled_amount();
+        if filled == 0 {
+            return None;
+        }
+        let remaining_ms = self
+            .age(now)
+            .num_milliseconds()
+            .checked_mul(i64::from(self.amount))?
+            .checked_div(i64::from(filled))?;
+        chrono::Duration::try_milliseconds(remaining_ms)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Ship {
+    pub ship_id: String,
+    /// Like "AVI-0123A"
+    pub registration: String,
+    /// The name given by the user, if any
+    pub name: Option<String>,
+    pub blueprint_natural_id: Option<String>,
+    /// The cargo hold, matching [`Storage::storage_id`]
+    #[serde(rename = "StoreId")]
+    pub cargo_store_id: String,
+    #[serde(rename = "StlFuelStoreId")]
+    pub stl_fuel_store_id: String,
+    #[serde(rename = "FtlFuelStoreId")]
+    pub ftl_fuel_store_id: String,
+    /// Between 0 and 1, where 1 is a perfectly maintained ship
+    pub condition: f32,
+    /// Where the ship is docked, or empty if it's in flight
+    #[serde(default)]
+    pub location: String,
+    /// The flight the ship is on, if any
+    pub flight_id: Option<String>,
+}
+
+impl Ship {
+    /// The name if it has one, otherwise the registration
+    pub fn display_name(&self) -> &str {
+        match &self.name {
+            Some(name) if !name.is_empty() => name,
+            _ => &self.registration,
+        }
+    }
+
+    pub fn in_flight(&self) -> bool {
+        self.flight_id.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Flight {
+    pub flight_id: String,
+    pub ship_id: String,
+    pub origin: String,
+    pub destination: String,
+    #[serde(rename = "DepartureTimeEpochMs", deserialize_with = "ms_to_date")]
+    pub departure: DateTime<Utc>,
+    /// Estimated time of arrival
+    #[serde(rename = "ArrivalTimeEpochMs", deserialize_with = "ms_to_date")]
+    pub arrival: DateTime<Utc>,
+    pub segments: Vec<FlightSegment>,
+    pub current_segment_index: usize,
+    #[serde(default)]
+    pub is_aborted: bool,
+}
+
+impl Flight {
+    /// The segment the ship is currently flying
+    pub fn current_segment(&self) -> Option<&FlightSegment> {
+        self.segments.get(self.current_segment_index)
+    }
+
+    /// How long until the ship arrives, or zero if it already did
+    pub fn time_remaining(&self, now: DateTime<Utc>) -> chrono::Duration {
+        (self.arrival - now).max(chrono::Duration::zero())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct FlightSegment {
+    /// Like "TAKE_OFF", "TRANSIT", "JUMP" or "LANDING"
+    #[serde(rename = "Type")]
+    pub segment_type: String,
+    pub origin: String,
+    pub destination: String,
+    #[serde(rename = "DepartureTimeEpochMs", deserialize_with = "ms_to_date")]
+    pub departure: DateTime<Utc>,
+    #[serde(rename = "ArrivalTimeEpochMs", deserialize_with = "ms_to_date")]
+    pub arrival: DateTime<Utc>,
+    #[serde(default)]
+    pub stl_distance: f32,
+    #[serde(default)]
+    pub ftl_distance: f32,
+}
+
+/// A company, as returned by `/user/{username}` or `/company/code/{code}`
+///
+/// Balances are only visible for the logged-in user and users who share their data with them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CompanyProfile {
+    pub user_name: Option<String>,
+    pub company_id: String,
+    pub company_name: String,
+    /// Like "EM32", as shown on local market ads and exchange orders
+    pub company_code: String,
+    pub country_code: Option<String>,
+    pub corporation_code: Option<String>,
+    /// The planet of the headquarters, like "UV-351a"
+    pub headquarters_natural_id: Option<String>,
+    pub headquarters_level: Option<u32>,
+    #[serde(default)]
+    pub balances: Vec<CurrencyBalance>,
+    #[serde(default)]
+    pub planets: Vec<CompanyPlanet>,
+    /// Ratings are letters from "A" (best) to "F", or "P" for pending
+    pub overall_rating: Option<String>,
+    pub activity_rating: Option<String>,
+    pub reliability_rating: Option<String>,
+    pub stability_rating: Option<String>,
+}
+
+impl CompanyProfile {
+    /// The balance in one currency, like "CIS"
+    pub fn balance(&self, currency: &str) -> Option<f64> {
+        self.balances
+            .iter()
+            .find(|b| b.currency == currency)
+            .map(|b| b.amount)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CurrencyBalance {
+    pub currency: String,
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CompanyPlanet {
+    pub planet_id: String,
+    /// Like "UV-351a"
+    pub planet_natural_id: String,
+    pub planet_name: String,
+}
+
+/// Which side of a contract someone is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ContractParty {
+    Customer,
+    Provider,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ContractStatus {
+    Open,
+    Closed,
+    PartiallyFulfilled,
+    Fulfilled,
+    Breached,
+    DeadlineExceeded,
+    Terminated,
+    Cancelled,
+    Rejected,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ConditionType {
+    Payment,
+    Delivery,
+    DeliveryShipment,
+    Provision,
+    ProvisionShipment,
+    PickupShipment,
+    ComexPurchasePickup,
+    LoanInstallment,
+    LoanPayout,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Contract {
+    pub contract_id: String,
+    /// The ID shown in-game, like "ABC-123"
+    pub contract_local_id: String,
+    pub name: Option<String>,
+    /// Our side of the contract
+    pub party: ContractParty,
+    pub partner_name: String,
+    pub partner_company_code: Option<String>,
+    pub status: ContractStatus,
+    #[serde(
+        rename = "DueDateEpochMs",
+        deserialize_with = "optional_ms_to_date",
+        default
+    )]
+    pub due_date: Option<DateTime<Utc>>,
+    pub conditions: Vec<ContractCondition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContractCondition {
+    #[serde(rename = "Type")]
+    pub condition_type: ConditionType,
+    /// Which side has to fulfill this condition
+    pub party: ContractParty,
+    pub material_ticker: Option<String>,
+    pub material_amount: Option<u32>,
+    /// Where the material has to be delivered or provided, like "UV-351a"
+    pub destination: Option<String>,
+    #[serde(
+        rename = "DeadlineEpochMs",
+        deserialize_with = "optional_ms_to_date",
+        default
+    )]
+    pub deadline: Option<DateTime<Utc>>,
+    #[serde(rename = "Status", deserialize_with = "condition_fulfilled")]
+    pub fulfilled: bool,
+}
+
+impl Contract {
+    /// Whether this contract still has conditions to fulfill
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self.status,
+            ContractStatus::Open | ContractStatus::Closed | ContractStatus::PartiallyFulfilled
+        )
+    }
+
+    /// Unfulfilled conditions that are our responsibility
+    pub fn our_pending_conditions(&self) -> impl Iterator<Item = &ContractCondition> {
+        self.conditions
+            .iter()
+            .filter(move |c| c.party == self.party && !c.fulfilled)
+    }
+
+    /// Our unfulfilled conditions whose deadline has passed
+    pub fn overdue_conditions(&self, now: DateTime<Utc>) -> Vec<&ContractCondition> {
+        self.our_pending_conditions()
+            .filter(|c| c.deadline.is_some_and(|d| d < now))
+            .collect()
+    }
+
+    /// Our unfulfilled conditions that are due within `within`, but aren't overdue yet
+    pub fn conditions_due_within(
+        &self,
+        now: DateTime<Utc>,
+        within: chrono::Duration,
+    ) -> Vec<&ContractCondition> {
+        self.our_pending_conditions()
+            .filter(|c| c.deadline.is_some_and(|d| d >= now && d < now + within))
+            .collect()
+    }
+
+    /// Map from material ticker to the amount we still have to deliver or provide, over all
+    /// active contracts
+    pub fn committed_materials(contracts: &[Contract]) -> HashMap<String, u32> {
+        let mut map = HashMap::new();
+        for contract in contracts.iter().filter(|c| c.is_active()) {
+            for cond in contract.our_pending_conditions() {
+                if let (Some(ticker), Some(amount)) = (&cond.material_ticker, cond.material_amount)
+                {
+                    *map.entry(ticker.clone()).or_default() += amount;
+                }
+            }
+        }
+        map
+    }
+}
+
+/// A ship together with its stores and the flight it's on
+#[derive(Debug, Clone)]
+pub struct ShipStatus {
+    pub ship: Ship,
+    pub cargo: Option<Storage>,
+    pub stl_fuel: Option<Storage>,
+    pub ftl_fuel: Option<Storage>,
+    pub flight: Option<Flight>,
+}
+
+impl ShipStatus {
+    /// Links ships to their stores and flights by ID
+    pub fn link(ships: Vec<Ship>, storages: &[Storage], flights: &[Flight]) -> Vec<Self> {
+        let store = |id: &str| storages.iter().find(|s| s.storage_id == id).cloned();
+        ships
+            .into_iter()
+            .map(|ship| Self {
+                cargo: store(&ship.cargo_store_id),
+                stl_fuel: store(&ship.stl_fuel_store_id),
+                ftl_fuel: store(&ship.ftl_fuel_store_id),
+                flight: flights.iter().find(|f| f.ship_id == ship.ship_id).cloned(),
+                ship,
+            })
+            .collect()
+    }
 }
 
 #[cfg(feature = "gendb")]
@@ -962,6 +2099,14 @@ impl quote::ToTokens for Recipe {
     }
 }
 
+fn condition_fulfilled<'de, D>(d: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: String = String::deserialize(d)?;
+    Ok(s == "FULFILLED")
+}
+
 fn optional_ms_to_date<'de, D>(d: D) -> Result<Option<DateTime<Utc>>, D::Error>
 where
     D: Deserializer<'de>,
@@ -1007,9 +2152,14 @@ where
 
 #[cfg(test)]
 mod tests {
+    use chrono::Utc;
     use serde_json::Value;
 
-    use crate::types::Ticker;
+    use crate::types::{
+        Contract, ExchangeSnapshot, InstantFill, MarketOrder, OrderBook, OrderStatus, OrderType,
+        OwnMarketOrder, Planet, PlanetInfrastructure, PlanetQuery, PriceHistory, PriceInterval,
+        Ticker, Workforce,
+    };
 
     use super::Storage;
 
@@ -1035,7 +2185,7 @@ mod tests {
         )
         .unwrap();
 
-        let sfci1 = Ticker::from_json(data).unwrap();
+        let sfci1: Ticker = serde_json::from_value(data).unwrap();
         // dbg!(sfci1);
 
         assert_eq!(267.0, sfci1.instant_buy(1).unwrap().total_value);
@@ -1046,14 +2196,339 @@ mod tests {
             sfci1.instant_buy(300).unwrap().total_value
         );
 
-        let cotci1 = Ticker::from_json(
-            serde_json::from_reader(
-                std::fs::File::open("test_data/exchange_COT_CI1.json").unwrap(),
-            )
-            .unwrap(),
+        let cotci1: Ticker = serde_json::from_reader(
+            std::fs::File::open("test_data/exchange_COT_CI1.json").unwrap(),
         )
         .unwrap();
 
         assert!(cotci1.instant_buy(1).is_none());
     }
+
+    #[test]
+    fn test_exchange_snapshot() {
+        let sf = std::fs::read_to_string("test_data/exchange_SF_CI1.json").unwrap();
+        let body =
+            format!(r#"[{sf}, {{"MaterialTicker": "RAT", "ExchangeCode": "CI1", "Ask": "oops"}}]"#);
+        let entries: Vec<Box<serde_json::value::RawValue>> = serde_json::from_str(&body).unwrap();
+
+        let snapshot = ExchangeSnapshot::from_raw(&entries, Utc::now());
+        assert!(snapshot.tickers.contains_key("SF.CI1"));
+        assert_eq!(snapshot.rejected.len(), 1);
+        assert_eq!(snapshot.rejected[0].index, 1);
+        assert_eq!(snapshot.rejected[0].id.as_deref(), Some("RAT.CI1"));
+    }
+
+    #[test]
+    fn test_price_history() {
+        let candle = |interval: &str, day: i64, close: f32, traded: u32| {
+            serde_json::json!({
+                "Interval": interval,
+                "DateEpochMs": day * 86_400_000,
+                "Open": close,
+                "Close": close,
+                "High": close,
+                "Low": close,
+                "Volume": close * traded as f32,
+                "Traded": traded,
+            })
+        };
+        let candles = serde_json::from_value(serde_json::json!([
+            candle("DAY_ONE", 3, 110.0, 10),
+            candle("DAY_ONE", 1, 50.0, 1),
+            candle("HOUR_ONE", 3, 500.0, 1),
+            candle("DAY_ONE", 2, 100.0, 30),
+            candle("WEEK_ONE", 3, 500.0, 1),
+        ]))
+        .unwrap();
+        let history = PriceHistory::new("RAT.CI1", candles);
+
+        assert_eq!(history.candles(PriceInterval::DayOne).count(), 3);
+        assert_eq!(history.candles(PriceInterval::Other).count(), 1);
+        let now = chrono::DateTime::from_timestamp(4 * 86_400, 0).unwrap();
+        assert_eq!(history.moving_average(2, now), Some(105.0));
+        assert_eq!(history.vwap(2, now), Some(4100.0 / 40.0));
+        assert_eq!(history.vwap(10, now), Some(4150.0 / 41.0));
+        assert!((history.volatility(2, now).unwrap()).abs() < 1e-6);
+        assert!(history.volatility(3, now).unwrap() > 0.4);
+        assert_eq!(history.volatility(1, now), None);
+
+        // old trades don't count, however few recent ones there are
+        let later = chrono::DateTime::from_timestamp(40 * 86_400, 0).unwrap();
+        assert_eq!(history.vwap(7, later), None);
+        assert_eq!(history.moving_average(7, later), None);
+    }
+
+    #[test]
+    fn test_contract_deadlines() {
+        let contract: Contract = serde_json::from_value(serde_json::json!({
+            "ContractId": "c1",
+            "ContractLocalId": "ABC-123",
+            "Name": null,
+            "Party": "PROVIDER",
+            "PartnerName": "Someone Else",
+            "PartnerCompanyCode": "SE",
+            "Status": "PARTIALLY_FULFILLED",
+            "DueDateEpochMs": null,
+            "Conditions": [
+                {
+                    "Type": "DELIVERY",
+                    "Party": "PROVIDER",
+                    "MaterialTicker": "RAT",
+                    "MaterialAmount": 500,
+                    "Destination": "UV-351a",
+                    "DeadlineEpochMs": 1_000_000,
+                    "Status": "PENDING",
+                },
+                {
+                    "Type": "DELIVERY",
+                    "Party": "PROVIDER",
+                    "MaterialTicker": "DW",
+                    "MaterialAmount": 200,
+                    "Destination": "UV-351a",
+                    "DeadlineEpochMs": 5_000_000,
+                    "Status": "PENDING",
+                },
+                {
+                    "Type": "PAYMENT",
+                    "Party": "CUSTOMER",
+                    "DeadlineEpochMs": 1_000_000,
+                    "Status": "PENDING",
+                },
+                {
+                    "Type": "SOMETHING_NEW",
+                    "Party": "PROVIDER",
+                    "Status": "FULFILLED",
+                },
+            ],
+        }))
+        .unwrap();
+
+        let now = chrono::DateTime::from_timestamp_millis(2_000_000).unwrap();
+        let overdue = contract.overdue_conditions(now);
+        assert_eq!(overdue.len(), 1);
+        assert_eq!(overdue[0].material_ticker.as_deref(), Some("RAT"));
+        assert!(contract
+            .conditions_due_within(now, chrono::Duration::seconds(1))
+            .is_empty());
+        assert_eq!(
+            contract
+                .conditions_due_within(now, chrono::Duration::hours(1))
+                .len(),
+            1
+        );
+
+        let committed = Contract::committed_materials(&[contract]);
+        assert_eq!(committed["RAT"], 500);
+        assert_eq!(committed["DW"], 200);
+    }
+
+    #[test]
+    fn test_infrastructure() {
+        let mut report = serde_json::Map::new();
+        for tier in ["Pioneer", "Settler", "Technician", "Engineer", "Scientist"] {
+            report.insert(format!("NextPopulation{tier}"), 1000.into());
+            report.insert(format!("UnemploymentRate{tier}"), 0.1.into());
+            report.insert(format!("OpenJobs{tier}"), 0.0.into());
+            report.insert(format!("AverageHappiness{tier}"), 0.8.into());
+        }
+        let mut latest = report.clone();
+        latest.insert("SimulationPeriod".into(), 12.into());
+        latest.insert("NextPopulationPioneer".into(), 2500.into());
+        report.insert("SimulationPeriod".into(), 11.into());
+
+        let infra: PlanetInfrastructure = serde_json::from_value(serde_json::json!({
+            "InfrastructureReports": [latest, report],
+            "InfrastructureProjects": [
+                {"Ticker": "COGC", "Name": "Chamber of Global Commerce", "Level": 1, "ActiveLevel": 1},
+            ],
+        }))
+        .unwrap();
+        assert_eq!(infra.population[&Workforce::Pioneers].count, 2500);
+        assert_eq!(infra.population[&Workforce::Scientists].count, 1000);
+        assert_eq!(infra.project_level("COGC"), 1);
+        assert_eq!(infra.project_level("PAC"), 0);
+
+        let planet: Planet = serde_json::from_value(serde_json::json!({
+            "PlanetName": "Montem",
+            "PlanetNaturalId": "UV-351a",
+            "PlanetId": "id",
+            "HasLocalMarket": true,
+            "LocalMarketFeeFactor": 1.0,
+            "FactionCode": "CI",
+            "CurrencyCode": "CIS",
+            "HasWarehouse": true,
+            "Resources": [],
+            "ProductionFees": [],
+            "COGCPrograms": [
+                {"ProgramType": "ADVERTISING_AGRICULTURE", "StartEpochMs": 0, "EndEpochMs": 1000},
+                {"ProgramType": null, "StartEpochMs": 2000, "EndEpochMs": 3000},
+                {"ProgramType": "WORKFORCE_PIONEERS", "StartEpochMs": 1000, "EndEpochMs": 2000},
+            ],
+        }))
+        .unwrap();
+        let now = chrono::DateTime::from_timestamp_millis(500).unwrap();
+        assert_eq!(
+            planet
+                .current_cogc_program(now)
+                .unwrap()
+                .program_type
+                .as_deref(),
+            Some("ADVERTISING_AGRICULTURE")
+        );
+        assert_eq!(
+            planet
+                .upcoming_cogc_program(now)
+                .unwrap()
+                .program_type
+                .as_deref(),
+            Some("WORKFORCE_PIONEERS")
+        );
+    }
+
+    #[test]
+    fn test_own_order() {
+        let order: OwnMarketOrder = serde_json::from_value(serde_json::json!({
+            "ExchangeCode": "CI1",
+            "OrderType": "SELLING",
+            "MaterialTicker": "RAT",
+            "Amount": 60,
+            "InitialAmount": 100,
+            "Limit": 40.0,
+            "LimitCurrency": "CIS",
+            "Status": "PARTIALLY_FILLED",
+            "CreatedEpochMs": 0,
+            "Trades": [
+                {"Amount": 10, "Price": 40.0, "TradeTimeEpochMs": 1000, "PartnerCode": "ABC"},
+                {"Amount": 30, "Price": 44.0, "TradeTimeEpochMs": 2000},
+            ],
+        }))
+        .unwrap();
+
+        assert_eq!(order.order_type, OrderType::Selling);
+        assert_eq!(order.status, OrderStatus::PartiallyFilled);
+        assert_eq!(order.filled_amount(), 40);
+        assert_eq!(order.realized_value(), 1720.0);
+        assert_eq!(order.average_fill_price(), Some(43.0));
+
+        let now = chrono::DateTime::from_timestamp(4 * 3600, 0).unwrap();
+        assert_eq!(
+            order.expected_time_to_fill(now),
+            Some(chrono::Duration::hours(6))
+        );
+        // decades of age times a huge remaining amount doesn't fit
+        let huge = OwnMarketOrder {
+            amount: u32::MAX - 1,
+            initial_amount: u32::MAX,
+            ..order.clone()
+        };
+        let now = chrono::DateTime::from_timestamp(100 * 365 * 86400, 0).unwrap();
+        assert_eq!(huge.expected_time_to_fill(now), None);
+
+        let order: OrderType = serde_json::from_value(serde_json::json!("CANCELLING")).unwrap();
+        assert_eq!(order, OrderType::Other);
+    }
+
+    #[test]
+    fn test_order_book() {
+        let order = |item_cost: f32, item_count: Option<u32>| MarketOrder {
+            company_code: Some("ABC".to_string()),
+            company_name: "Somebody".to_string(),
+            item_cost,
+            item_count,
+        };
+        let book = OrderBook::new(
+            vec![
+                order(90.0, Some(100)),
+                order(95.0, Some(50)),
+                order(10.0, None),
+            ],
+            vec![order(110.0, Some(100)), order(105.0, Some(50))],
+        );
+
+        assert_eq!(book.best_bid(), Some(95.0));
+        assert_eq!(book.best_ask(), Some(105.0));
+        assert_eq!(book.mid_price(), Some(100.0));
+        assert_eq!(book.spread(), Some(10.0));
+        assert_eq!(book.spread_percent(), Some(10.0));
+        assert_eq!(book.depth_within(5.0), Some((50, 50)));
+        assert_eq!(book.depth_within(10.0), Some((150, 150)));
+        assert_eq!(book.imbalance(10.0), Some(0.0));
+        assert_eq!(book.liquidity_score(), Some(100.0 * 100.0 / 11.0));
+
+        assert_eq!(book.vwap(OrderType::Buying, 100), Some(107.5));
+        assert_eq!(book.vwap(OrderType::Buying, 200), None);
+        // the market maker buys everything else
+        assert_eq!(book.instant_sell(1000).unwrap().price_limit, 10.0);
+        // nothing to buy costs nothing, at the best price
+        let none = book.instant_buy(0).unwrap();
+        assert_eq!((none.total_value, none.price_limit), (0.0, 105.0));
+        assert!(OrderBook::default().instant_buy(0).is_none());
+
+        let fill = book.fill_buy(200, true);
+        assert!(!fill.is_complete());
+        assert_eq!((fill.filled, fill.unfilled), (150, 50));
+        assert_eq!(fill.levels, [(105.0, 50), (110.0, 100)]);
+        assert_eq!(fill.total_value, 105.0 * 50.0 + 110.0 * 100.0);
+        assert_eq!(fill.worst_price, Some(110.0));
+        assert_eq!(
+            fill.total_with_fallback(120.0),
+            fill.total_value + 120.0 * 50.0
+        );
+
+        let fill = book.fill_sell(1000, false);
+        assert_eq!((fill.filled, fill.unfilled), (150, 850));
+        assert_eq!(fill.worst_price, Some(90.0));
+        let fill = book.fill_sell(1000, true);
+        assert!(fill.is_complete());
+        assert_eq!(fill.levels.last(), Some(&(10.0, 850)));
+        assert_eq!(book.fill_buy(0, true), InstantFill::default());
+
+        let curve = book.impact_curve(OrderType::Buying, &[50, 100, 1000]);
+        assert_eq!(curve.len(), 2);
+        assert_eq!(curve[0].impact, 0.0);
+        assert!((curve[1].impact - 2.5 / 105.0).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_search_planets() {
+        let (server, client) = crate::testing::mock_client().await.unwrap();
+        let planet = |natural_id: &str, gravity: f32, fertility: f32, h2o: f32| {
+            let mut planet = crate::testing::planet_json(natural_id);
+            planet["Gravity"] = gravity.into();
+            planet["Pressure"] = 1.0.into();
+            planet["Temperature"] = 20.0.into();
+            planet["Surface"] = true.into();
+            planet["Fertility"] = fertility.into();
+            planet["Resources"] = serde_json::json!([{
+                "MaterialId": "ec8dbb1d3f51d89c61b6f58fdd64a7f0",
+                "ResourceType": "LIQUID",
+                "Factor": h2o,
+            }]);
+            planet
+        };
+        server.route(
+            "/planet/allplanets/full",
+            serde_json::json!([
+                planet("AA-001a", 1.0, 0.1, 0.4),
+                planet("AA-001b", 3.0, 0.1, 0.4),
+                planet("AA-001c", 1.0, -1.0, 0.4),
+                planet("AA-001d", 1.0, 0.1, 0.1),
+                {"PlanetNaturalId": "AA-001e"},
+            ]),
+        );
+
+        let query = PlanetQuery {
+            include_high_gravity: false,
+            must_be_fertile: true,
+            ..Default::default()
+        }
+        .resource("H2O", 0.2);
+        let found = client.search_planets(&query).await.unwrap();
+        let ids: Vec<_> = found.iter().map(|p| p.natural_id.as_str()).collect();
+        assert_eq!(ids, ["AA-001a"]);
+
+        let all = client.get_all_planets().await.unwrap();
+        assert_eq!(all.entries.len(), 4);
+        assert_eq!(all.rejected[0].id.as_deref(), Some("AA-001e"));
+    }
 }